@@ -0,0 +1,106 @@
+//! Times compiling and running representative scripts separately, with both backends:
+//!
+//! ```text
+//! cargo bench --bench interpreter
+//! ```
+
+use iron::engine::*;
+use iron::vm::*;
+use std::time::{Duration, Instant};
+
+const COMPILE_RUNS: u32 = 200;
+const RUNS: u32 = 10;
+
+const CASES: &[(&str, &str)] = &[
+    (
+        "fibonacci",
+        "fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } } fib(20)",
+    ),
+    (
+        "loop arithmetic",
+        "let sum = 0; let i = 0; while i < 100000 { sum = (sum + i * 3) % 1000; i += 1; } sum",
+    ),
+    (
+        "string building",
+        "let s = \"\"; for i in 0..2000 { s = s + i + \",\"; } s.len()",
+    ),
+    (
+        "array push/iterate",
+        "let a = arr(); for i in 0..10000 { a.push(i * 2); } \
+         let sum = 0; for x in a { sum += x; } sum",
+    ),
+    (
+        "host methods",
+        "let p = vec2(0.0, 0.0); let step = vec2(1.0, 0.5); \
+         for i in 0..5000 { p.add(step); p.scale(0.99); } p.length()",
+    ),
+];
+
+#[derive(Clone, Copy)]
+struct Vec2 {
+    x: f32,
+    y: f32,
+}
+
+fn engine(backend: Backend) -> Engine<()> {
+    let mut engine = Engine::new();
+
+    engine
+        .set_backend(backend)
+        .register_fn("vec2", |x: f32, y: f32| Vec2 { x, y })
+        .register_fn("add", |v: &mut Vec2, other: Vec2| {
+            v.x += other.x;
+            v.y += other.y;
+        })
+        .register_fn("scale", |v: &mut Vec2, k: f32| {
+            v.x *= k;
+            v.y *= k;
+        })
+        .register_fn("length", |v: &mut Vec2| (v.x * v.x + v.y * v.y).sqrt());
+
+    engine
+}
+
+fn mean(runs: u32, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+
+    for _ in 0..runs {
+        f();
+    }
+
+    start.elapsed() / runs
+}
+
+fn main() {
+    let walker = engine(Backend::TreeWalker);
+    let vm = engine(Backend::Vm);
+
+    println!("mean of {} compiles and {} runs", COMPILE_RUNS, RUNS);
+    println!(
+        "{:<20}{:>12}{:>14}{:>12}",
+        "", "compile", "tree walker", "vm"
+    );
+
+    for (name, source) in CASES {
+        let script = walker.compile(*source).unwrap();
+
+        // warm up, and make sure both backends agree
+        let expected = walker.run(&mut (), &script).unwrap().to_string();
+        assert_eq!(vm.run(&mut (), &script).unwrap().to_string(), expected);
+
+        let compile = mean(COMPILE_RUNS, || {
+            walker.compile(*source).unwrap();
+        });
+        let walk = mean(RUNS, || {
+            walker.run(&mut (), &script).unwrap();
+        });
+        let run = mean(RUNS, || {
+            vm.run(&mut (), &script).unwrap();
+        });
+
+        println!(
+            "{:<20}{:>12.3?}{:>14.3?}{:>12.3?}",
+            name, compile, walk, run
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Times scripts that lean on shared values, run it once per mode to compare them:
+//!
+//! ```text
+//! cargo bench --bench shared
+//! cargo bench --bench shared --features unsync
+//! ```
+
+use iron::engine::*;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 20;
+
+const CASES: &[(&str, &str)] = &[
+    (
+        "array push",
+        "let a = arr(); let i = 0; while i < 5000 { a.push(i); i += 1; } a.len()",
+    ),
+    (
+        "references",
+        "let x = 0; let r = &x; let i = 0; while i < 5000 { *r = *r + i; i += 1; } x",
+    ),
+    (
+        "closure captures",
+        "let a = arr(); let add = |v| a.push(v); let i = 0; \
+         while i < 5000 { add(i); i += 1; } a.len()",
+    ),
+    (
+        "nested arrays",
+        "let rows = arr(); let i = 0; \
+         while i < 500 { let row = arr(); for j in 0..10 { row.push(j); } rows.push(row); i += 1; } \
+         rows.len()",
+    ),
+];
+
+fn time(engine: &Engine<()>, source: &str) -> Duration {
+    // warm up, and make sure the case actually runs
+    engine.eval(&mut (), source).unwrap();
+
+    let start = Instant::now();
+
+    for _ in 0..RUNS {
+        engine.eval(&mut (), source).unwrap();
+    }
+
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let mode = if cfg!(feature = "unsync") {
+        "Rc<RefCell<..>>"
+    } else {
+        "Arc<RwLock<..>>"
+    };
+
+    println!("shared values through {}, mean of {} runs", mode, RUNS);
+
+    let engine = Engine::<()>::new();
+
+    for (name, source) in CASES {
+        println!("{:<20}{:>12.3?}", name, time(&engine, source));
+    }
+}
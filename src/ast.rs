@@ -1,8 +1,15 @@
 use crate::fn_storage::*;
+use crate::range::*;
+use crate::scope::*;
 use crate::span::*;
+use crate::symbol::*;
 use crate::variant::*;
 use std::sync::Arc;
 
+/// Numbers a function call or operator in a program, the function it dispatched to is
+/// cached under it. Assigned by [`resolve`](crate::resolve::resolve).
+pub type CallSite = usize;
+
 #[derive(Clone, Debug)]
 pub struct Block {
     pub stmts: Vec<Spanned<Stmt>>,
@@ -12,7 +19,7 @@ pub struct Block {
 #[derive(Clone, Debug)]
 pub enum Stmt {
     Let {
-        ident: String,
+        ident: Symbol,
         ty: Option<UnionType>,
         expr: Spanned<Expr>,
     },
@@ -24,7 +31,7 @@ pub enum Stmt {
     FnDef {
         fn_signature: FnSignature,
         block: Arc<Spanned<Block>>,
-        parameter_idents: Arc<Vec<String>>,
+        parameter_idents: Arc<Vec<Symbol>>,
         return_type: UnionType,
     },
 }
@@ -35,8 +42,26 @@ pub enum Expr {
         variant: Union,
     },
 
+    /// A value computed by [`optimize`](crate::optimize::optimize), unlike a literal
+    /// it's typed like the result of the operation it replaced.
+    Constant {
+        variant: Union,
+    },
+
+    Array {
+        items: Vec<Spanned<Expr>>,
+    },
+
+    Closure {
+        parameter_idents: Arc<Vec<Symbol>>,
+        body: Arc<Spanned<Expr>>,
+    },
+
+    /// `slot` is [`None`] until resolved, and stays so for module constants and
+    /// variables that don't exist.
     Variable {
-        ident: Spanned<String>,
+        ident: Spanned<Symbol>,
+        slot: Option<Slot>,
     },
 
     Assign {
@@ -51,7 +76,8 @@ pub enum Expr {
     BinOp {
         lhs: Box<Spanned<Expr>>,
         rhs: Box<Spanned<Expr>>,
-        op: Spanned<String>,
+        op: Spanned<Symbol>,
+        site: CallSite,
     },
 
     Reference {
@@ -62,6 +88,11 @@ pub enum Expr {
         expr: Box<Spanned<Expr>>,
     },
 
+    /// `expr?`, unwraps `some`/`ok` or returns `none`/`err` from the current function.
+    Try {
+        expr: Box<Spanned<Expr>>,
+    },
+
     Block {
         block: Box<Block>,
     },
@@ -72,15 +103,20 @@ pub enum Expr {
         else_block: Option<Box<Spanned<Expr>>>,
     },
 
+    /// `slot` is the variable called when no function matches, eg. one holding a
+    /// closure.
     FnCall {
-        ident: Spanned<String>,
+        ident: Spanned<Symbol>,
         params: Vec<Spanned<Expr>>,
+        slot: Option<Slot>,
+        site: CallSite,
     },
 
     MethodCall {
-        ident: Spanned<String>,
+        ident: Spanned<Symbol>,
         caller: Box<Spanned<Expr>>,
         params: Vec<Spanned<Expr>>,
+        site: CallSite,
     },
 
     TryCatch {
@@ -94,8 +130,33 @@ pub enum Expr {
     },
 
     ForLoop {
-        ident: Spanned<String>,
+        ident: Spanned<Symbol>,
         expr: Box<Spanned<Expr>>,
         block: Box<Spanned<Block>>,
     },
+
+    Range {
+        start: Option<Box<Spanned<Expr>>>,
+        end: Option<Box<Spanned<Expr>>>,
+        inclusive: bool,
+    },
+
+    Match {
+        expr: Box<Spanned<Expr>>,
+        arms: Vec<MatchArm>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchArm {
+    pub pattern: Spanned<Pattern>,
+    pub expr: Spanned<Expr>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Wildcard,
+    Binding(Symbol),
+    Literal(Union),
+    Range(Range),
 }
@@ -8,9 +8,24 @@ fn main() {
     let mut ctx = ();
     let mut engine = Engine::new();
 
-    engine.register_fn("print", |union: Union| println!("{}", union));
+    engine.register_fn(
+        "print",
+        |runtime: &mut Runtime<()>, scope: &mut Scope<()>, union: Union| {
+            runtime
+                .display(&union, scope)
+                .map(|formatted| println!("{}", formatted))
+        },
+    );
 
-    engine.eval_file(&mut ctx, &args[1]).unwrap();
+    match engine.eval_file(&mut ctx, &args[1]) {
+        Err(Error {
+            kind: ErrorKind::Exit(code),
+            ..
+        }) => std::process::exit(code),
+        result => {
+            result.unwrap();
+        }
+    }
 
     println!("{:?}", std::time::Instant::now() - now);
 }
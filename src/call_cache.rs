@@ -0,0 +1,103 @@
+use crate::ast::*;
+use crate::function::*;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::symbol::*;
+use crate::variant::*;
+
+/// What a call site dispatched to the last time, valid as long as it calls the same
+/// function with arguments of the same types from a scope of the same generation.
+pub(crate) struct CallCache<T> {
+    ident: Symbol,
+    generation: u64,
+    params: Vec<UnionType>,
+    target: CallTarget<T>,
+}
+
+pub(crate) struct CallTarget<T> {
+    /// [`None`] for operators without an overload, which fall back to the built in ones.
+    pub fn_type: Option<FnType<T>>,
+    /// Whether a method takes its caller by reference.
+    pub by_reference: bool,
+}
+
+impl<T> Clone for CallTarget<T> {
+    fn clone(&self) -> Self {
+        Self {
+            fn_type: self.fn_type.clone(),
+            by_reference: self.by_reference,
+        }
+    }
+}
+
+impl<'a, T> Runtime<'a, T> {
+    /// Gets what `site` dispatched to the last time, if it's still valid for calling
+    /// `ident` with `params`.
+    #[inline(always)]
+    pub(crate) fn cached_call(
+        &self,
+        site: CallSite,
+        ident: Symbol,
+        params: &[Variable],
+        scope: &Scope<T>,
+    ) -> Option<CallTarget<T>> {
+        let cache = self.call_caches.get(site)?.as_ref()?;
+
+        let hit = cache.ident == ident
+            && cache.generation == scope.generation()
+            && cache.params.len() == params.len()
+            && params
+                .iter()
+                .zip(&cache.params)
+                .all(|(param, ty)| param.ty() == *ty);
+
+        if hit {
+            Some(cache.target.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Remembers what `site` dispatched to for arguments of the types `params`.
+    pub(crate) fn cache_call(
+        &mut self,
+        site: CallSite,
+        ident: Symbol,
+        params: Vec<UnionType>,
+        scope: &Scope<T>,
+        target: CallTarget<T>,
+    ) {
+        if self.call_caches.len() <= site {
+            self.call_caches.resize_with(site + 1, || None);
+        }
+
+        self.call_caches[site] = Some(CallCache {
+            ident,
+            generation: scope.generation(),
+            params,
+            target,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::*;
+
+    #[test]
+    fn invalidated_by_new_functions() {
+        // the second time around, each call site must see the overload defined by the
+        // first
+        assert_eq!(
+            eval(
+                "fn g(x) { \"any\" } fn m(x) { \"any\" } let s = \"\"; let i = 0; \
+                 while i < 2 { \
+                     s = s + g(1) + 1.m(); \
+                     if i == 0 { fn g(x: i32) { \"int\" } fn m(x: i32) { \"int\" } } \
+                     i += 1; \
+                 } s"
+            ),
+            "anyanyintint"
+        );
+    }
+}
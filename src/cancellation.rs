@@ -0,0 +1,110 @@
+use crate::control_flow::*;
+use crate::error::*;
+use crate::runtime::*;
+use crate::shared::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How many operations pass between calls to the progress callback.
+pub const PROGRESS_INTERVAL: u64 = 1024;
+
+maybe_sync_dyn! {
+    type Progress = dyn Fn(u64) -> bool;
+}
+
+/// Called with the number of operations so far, returning `false` stops the run.
+pub type ProgressFn = Arc<Progress>;
+
+/// Stops a running script from another thread, all clones share the same flag.
+///
+/// Checked at every loop iteration and function call, so a script stops within one
+/// iteration of [`CancellationToken::cancel`] unless it's blocked inside the host.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Lets the token be used for another run.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
+
+fn cancelled(message: &str) -> ControlFlow {
+    Error::from_raw(ErrorKind::Cancelled, message).into()
+}
+
+impl<'a, T> Runtime<'a, T> {
+    #[inline(always)]
+    pub(crate) fn check_cancelled(&self) -> Result<(), ControlFlow> {
+        match &self.cancellation_token {
+            Some(token) if token.is_cancelled() => Err(cancelled("cancelled by the host")),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn report_progress(&self) -> Result<(), ControlFlow> {
+        match &self.on_progress {
+            Some(on_progress) if !on_progress(self.operations) => {
+                Err(cancelled("cancelled by the progress callback"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::*;
+    use std::time::Duration;
+
+    #[test]
+    fn progress() {
+        let mut engine = Engine::<()>::new();
+        engine.on_progress(|ops| ops < 10 * PROGRESS_INTERVAL);
+
+        let error = engine.eval(&mut (), "while true {}").unwrap_err();
+
+        assert!(matches!(error.kind, ErrorKind::Cancelled));
+    }
+
+    #[test]
+    fn cancel_from_another_thread() {
+        let token = CancellationToken::new();
+
+        let mut engine = Engine::<()>::new();
+        engine.set_cancellation_token(token.clone());
+
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            token.cancel();
+        });
+
+        let error = engine
+            .eval(&mut (), "let i = 0; while true { i += 1; }")
+            .unwrap_err();
+
+        assert!(matches!(error.kind, ErrorKind::Cancelled));
+
+        canceller.join().unwrap();
+
+        // function entry is checked too
+        let error = engine.eval(&mut (), "fn f() { 1 } f()").unwrap_err();
+
+        assert!(matches!(error.kind, ErrorKind::Cancelled));
+    }
+}
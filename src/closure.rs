@@ -0,0 +1,74 @@
+use crate::ast::*;
+use crate::control_flow::*;
+use crate::error::*;
+use crate::limits::*;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::span::*;
+use crate::symbol::*;
+use crate::variant::*;
+use crate::vm::*;
+use std::sync::Arc;
+
+/// A closure created by `|a, b| expr`, holding shared handles to the variables that
+/// were visible where it was created, in the order their slots were resolved.
+#[derive(Debug)]
+pub struct Closure {
+    pub parameter_idents: Arc<Vec<Symbol>>,
+    pub body: Arc<Spanned<Expr>>,
+    pub captured: Vec<(Symbol, Variable)>,
+    /// The body compiled for the VM, [`None`] when created by the tree walker.
+    pub chunk: Option<Arc<Chunk>>,
+}
+
+impl Clone for Closure {
+    // clones keep referring to the same captured variables
+    fn clone(&self) -> Self {
+        Self {
+            parameter_idents: self.parameter_idents.clone(),
+            body: self.body.clone(),
+            captured: self
+                .captured
+                .iter()
+                .map(|(ident, variable)| (*ident, variable.clone_shared()))
+                .collect(),
+            chunk: self.chunk.clone(),
+        }
+    }
+}
+
+impl<'a, T> Runtime<'a, T> {
+    pub fn call_closure(
+        &mut self,
+        closure: &Closure,
+        input: Vec<Variable>,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        if input.len() != closure.parameter_idents.len() {
+            return Err(Error::new(ErrorKind::InvalidArgument, &self.source, closure.body.span).into());
+        }
+
+        self.enter_call()?;
+        scope.sub(true);
+
+        for (ident, variable) in &closure.captured {
+            scope.push(*ident, variable.clone_shared());
+        }
+
+        for (ident, variable) in closure.parameter_idents.iter().zip(input) {
+            scope.push(*ident, variable);
+        }
+
+        let returned = self
+            .check_variables(scope)
+            .and_then(|_| grow_stack(|| self.eval_closure_body(&closure.body, closure.chunk.as_deref(), scope)));
+
+        scope.rev_sub();
+        self.exit_call();
+
+        match returned {
+            Ok(variable) | Err(ControlFlow::Return(variable)) => Ok(variable),
+            Err(err) => Err(err),
+        }
+    }
+}
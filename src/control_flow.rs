@@ -4,6 +4,7 @@ use crate::variant::*;
 pub enum ControlFlow {
     Error(Error),
     Return(Variable),
+    Exit(i32),
 }
 
 impl From<Error> for ControlFlow {
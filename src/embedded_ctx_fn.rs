@@ -1,13 +1,19 @@
+use crate::control_flow::*;
 use crate::fn_storage::*;
 use crate::function::*;
+use crate::shared::*;
 use crate::variant::*;
 use std::sync::Arc;
 
 /// Used to denote which type of function the [`IntoEmbeddedFn`] should turn into.
 pub struct EmbCtxFn;
 
+maybe_sync_dyn! {
+    type Runner<T> = dyn Fn(&mut T, Vec<Variable>) -> Result<UnionCell, ControlFlow>;
+}
+
 pub struct EmbeddedCtxFn<T> {
-    runner: Arc<dyn Fn(&mut T, Vec<Variable>) -> Result<UnionCell, ()>>,
+    runner: Arc<Runner<T>>,
 }
 
 impl<T> std::fmt::Debug for EmbeddedCtxFn<T> {
@@ -27,18 +33,18 @@ impl<T> Clone for EmbeddedCtxFn<T> {
 }
 
 impl<T> EmbeddedCtxFn<T> {
-    pub fn run(&self, ctx: &mut T, input: Vec<Variable>) -> Result<UnionCell, ()> {
+    pub fn run(&self, ctx: &mut T, input: Vec<Variable>) -> Result<UnionCell, ControlFlow> {
         (self.runner)(ctx, input)
     }
 }
 
 macro_rules! def_register {
     ($($ident:ident),*) => {
-        impl<T, $($ident,)* R, F> IntoEmbeddedFn<T, ($(&$ident,)*), R, EmbCtxFn> for F
+        impl<T, $($ident,)* R, O, F> IntoEmbeddedFn<T, ($(&$ident,)*), (R, O), EmbCtxFn> for F
         where
             $($ident: EmbeddedFnParameter<$ident>,)*
-            R: Variant,
-            F: Fn(&mut T, $($ident,)*) -> R + 'static,
+            R: IntoFnResult<O>,
+            F: Fn(&mut T, $($ident,)*) -> R + MaybeSync + 'static,
         {
             #[inline(always)]
             fn into_embedded_fn(self) -> FnType<T> {
@@ -49,20 +55,20 @@ macro_rules! def_register {
 
                             $(
                                 #[allow(non_snake_case)]
-                                let $ident = $ident::map(&mut _iter.next().unwrap()).ok_or(())?;
+                                let $ident = $ident::map(&mut _iter.next().unwrap()).ok_or_else(unreachable)?;
                             )*
 
-                            Ok(UnionCell::new(self(ctx, $($ident,)*)))
+                            self(ctx, $($ident,)*).into_fn_result()
                         }),
                     }
                 )
             }
         }
 
-        impl<T, $($ident,)* R, F> IntoFnParameters<(&mut T, $(&$ident,)*), R, EmbCtxFn> for F
+        impl<T, $($ident,)* R, O, F> IntoFnParameters<(&mut T, $(&$ident,)*), (R, O), EmbCtxFn> for F
         where
             $($ident: EmbeddedFnParameter<$ident> + 'static,)*
-            R: Variant,
+            R: IntoFnResult<O>,
             F: Fn(&mut T, $($ident,)*) -> R + 'static,
         {
             #[inline(always)]
@@ -71,12 +77,12 @@ macro_rules! def_register {
             }
         }
 
-        impl<T, M, $($ident,)* R, F> IntoEmbeddedFn<T, (&mut M, $(&$ident,)*), R, EmbCtxFn> for F
+        impl<T, M, $($ident,)* R, O, F> IntoEmbeddedFn<T, (&mut M, $(&$ident,)*), (R, O), EmbCtxFn> for F
         where
             M: Variant,
             $($ident: Variant + Clone,)*
-            R: Variant,
-            F: Fn(&mut T, &mut M, $($ident,)*) -> R + 'static,
+            R: IntoFnResult<O>,
+            F: Fn(&mut T, &mut M, $($ident,)*) -> R + MaybeSync + 'static,
         {
             #[inline(always)]
             fn into_embedded_fn(self) -> FnType<T> {
@@ -87,18 +93,24 @@ macro_rules! def_register {
 
                             let mut var = match _iter.next().unwrap().cloned() {
                                 Union::Reference(var) => var,
-                                _ => return Err(()),
+                                _ => return Err(unreachable().into()),
                             };
 
+                            // read the arguments before locking, they might refer to the same value
+                            $(
+                                #[allow(non_snake_case)]
+                                let $ident: $ident = by_value(_iter.next().unwrap().cloned());
+                            )*
+
+                            let mut _args = Some(($($ident,)*));
+
                             var.map_mut(|v| {
-                                let m = v.downcast_mut::<M>().ok_or(())?;
+                                let m = v.downcast_mut::<M>().ok_or_else(unreachable)?;
 
-                                $(
-                                    #[allow(non_snake_case)]
-                                    let $ident = by_value(_iter.next().unwrap().cloned());
-                                )*
+                                #[allow(non_snake_case)]
+                                let ($($ident,)*) = _args.take().unwrap();
 
-                                Ok(UnionCell::new(self(ctx, m, $($ident,)*)))
+                                self(ctx, m, $($ident,)*).into_fn_result()
                             })
                         }),
                     }
@@ -106,11 +118,11 @@ macro_rules! def_register {
             }
         }
 
-        impl<T, M, $($ident,)* R, F> IntoFnParameters<(&mut T, &mut M, $(&$ident,)*), R, EmbCtxFn> for F
+        impl<T, M, $($ident,)* R, O, F> IntoFnParameters<(&mut T, &mut M, $(&$ident,)*), (R, O), EmbCtxFn> for F
         where
             $($ident: Variant,)*
             M: Variant,
-            R: Variant,
+            R: IntoFnResult<O>,
             F: Fn(&mut T, &mut M, $($ident,)*) -> R + 'static,
         {
             #[inline(always)]
@@ -1,14 +1,20 @@
+use crate::control_flow::*;
 use crate::fn_storage::*;
 use crate::function::*;
+use crate::shared::*;
 use crate::variant::*;
 use std::sync::Arc;
 
 /// Used to denote which type of function the [`IntoEmbeddedFn`] should turn into.
 pub struct EmbFn;
 
+maybe_sync_dyn! {
+    type Runner = dyn Fn(Vec<Variable>) -> Result<UnionCell, ControlFlow>;
+}
+
 #[derive(Clone)]
 pub struct EmbeddedFn {
-    runner: Arc<dyn Fn(Vec<Variable>) -> Result<UnionCell, ()>>,
+    runner: Arc<Runner>,
 }
 
 impl std::fmt::Debug for EmbeddedFn {
@@ -20,18 +26,18 @@ impl std::fmt::Debug for EmbeddedFn {
 }
 
 impl EmbeddedFn {
-    pub fn run(&self, input: Vec<Variable>) -> Result<UnionCell, ()> {
+    pub fn run(&self, input: Vec<Variable>) -> Result<UnionCell, ControlFlow> {
         (self.runner)(input)
     }
 }
 
 macro_rules! def_register {
     ($($ident:ident),*) => {
-        impl<T, $($ident,)* R, F> IntoEmbeddedFn<T, ($(&$ident,)*), R, EmbFn> for F
+        impl<T, $($ident,)* R, O, F> IntoEmbeddedFn<T, ($(&$ident,)*), (R, O), EmbFn> for F
         where
             $($ident: EmbeddedFnParameter<$ident> + 'static,)*
-            R: Variant,
-            F: Fn($($ident,)*) -> R + 'static,
+            R: IntoFnResult<O>,
+            F: Fn($($ident,)*) -> R + MaybeSync + 'static,
         {
             #[inline(always)]
             fn into_embedded_fn(self) -> FnType<T> {
@@ -42,20 +48,20 @@ macro_rules! def_register {
 
                             $(
                                 #[allow(non_snake_case)]
-                                let $ident = $ident::map(&mut _iter.next().unwrap()).ok_or(())?;
+                                let $ident = $ident::map(&mut _iter.next().unwrap()).ok_or_else(unreachable)?;
                             )*
 
-                            Ok(UnionCell::new(self($($ident),*)))
+                            self($($ident),*).into_fn_result()
                         }),
                     }
                 )
             }
         }
 
-        impl<$($ident,)* R, F> IntoFnParameters<($(&$ident,)*), R, EmbFn> for F
+        impl<$($ident,)* R, O, F> IntoFnParameters<($(&$ident,)*), (R, O), EmbFn> for F
         where
             $($ident: EmbeddedFnParameter<$ident> + 'static,)*
-            R: Variant,
+            R: IntoFnResult<O>,
             F: Fn($($ident,)*) -> R + 'static,
         {
             #[inline(always)]
@@ -64,12 +70,12 @@ macro_rules! def_register {
             }
         }
 
-        impl<T, M, $($ident,)* R, F> IntoEmbeddedFn<T, (&mut M, $(&$ident,)*), R, EmbFn> for F
+        impl<T, M, $($ident,)* R, O, F> IntoEmbeddedFn<T, (&mut M, $(&$ident,)*), (R, O), EmbFn> for F
         where
             $($ident: Variant + Clone,)*
             M: Variant,
-            R: Variant,
-            F: Fn(&mut M, $($ident,)*) -> R + 'static,
+            R: IntoFnResult<O>,
+            F: Fn(&mut M, $($ident,)*) -> R + MaybeSync + 'static,
         {
             #[inline(always)]
             fn into_embedded_fn(self) -> FnType<T> {
@@ -80,18 +86,24 @@ macro_rules! def_register {
 
                             let mut var = match _iter.next().unwrap().cloned() {
                                 Union::Reference(var) => var,
-                                _ => return Err(()),
+                                _ => return Err(unreachable().into()),
                             };
 
+                            // read the arguments before locking, they might refer to the same value
+                            $(
+                                #[allow(non_snake_case)]
+                                let $ident: $ident = by_value(_iter.next().unwrap().cloned());
+                            )*
+
+                            let mut _args = Some(($($ident,)*));
+
                             var.map_mut(|a| {
                                 let a: &mut M = a.downcast_mut().expect("Should be unreachable");
 
-                                $(
-                                    #[allow(non_snake_case)]
-                                    let $ident = by_value(_iter.next().unwrap().cloned());
-                                )*
+                                #[allow(non_snake_case)]
+                                let ($($ident,)*) = _args.take().unwrap();
 
-                                Ok(UnionCell::new(self(a, $($ident),*)))
+                                self(a, $($ident),*).into_fn_result()
                             })
                         }),
                     }
@@ -99,11 +111,11 @@ macro_rules! def_register {
             }
         }
 
-        impl<M, $($ident,)* R, F> IntoFnParameters<(&mut M, $(&$ident,)*), R, EmbFn> for F
+        impl<M, $($ident,)* R, O, F> IntoFnParameters<(&mut M, $(&$ident,)*), (R, O), EmbFn> for F
         where
             $($ident: Variant,)*
             M: Variant,
-            R: Variant,
+            R: IntoFnResult<O>,
             F: Fn(&mut M, $($ident,)*) -> R + 'static,
         {
             #[inline(always)]
@@ -0,0 +1,100 @@
+use crate::control_flow::*;
+use crate::fn_storage::*;
+use crate::function::*;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::shared::*;
+use crate::variant::*;
+use std::sync::Arc;
+
+/// Used to denote which type of function the [`IntoEmbeddedFn`] should turn into.
+pub struct EmbRuntimeFn;
+
+maybe_sync_dyn! {
+    type RuntimeRunner<T> =
+        dyn Fn(&mut Runtime<T>, &mut Scope<T>, Vec<Variable>) -> Result<UnionCell, ControlFlow>;
+}
+
+/// An embedded function with access to the [`Runtime`] and [`Scope`] it's called from,
+/// allowing it to call back into scripts, eg. to run a closure.
+pub struct EmbeddedRuntimeFn<T> {
+    runner: Arc<RuntimeRunner<T>>,
+}
+
+impl<T> std::fmt::Debug for EmbeddedRuntimeFn<T> {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "EmbeddedRuntimeFn")?;
+
+        Ok(())
+    }
+}
+
+impl<T> Clone for EmbeddedRuntimeFn<T> {
+    fn clone(&self) -> Self {
+        Self {
+            runner: self.runner.clone(),
+        }
+    }
+}
+
+impl<T> EmbeddedRuntimeFn<T> {
+    pub fn run(
+        &self,
+        runtime: &mut Runtime<T>,
+        scope: &mut Scope<T>,
+        input: Vec<Variable>,
+    ) -> Result<UnionCell, ControlFlow> {
+        (self.runner)(runtime, scope, input)
+    }
+}
+
+macro_rules! def_register {
+    ($($ident:ident),*) => {
+        impl<T, $($ident,)* R, O, F> IntoEmbeddedFn<T, (&Scope<T>, $(&$ident,)*), (R, O), EmbRuntimeFn> for F
+        where
+            $($ident: EmbeddedFnParameter<$ident>,)*
+            R: IntoFnResult<O>,
+            F: Fn(&mut Runtime<T>, &mut Scope<T>, $($ident,)*) -> R + MaybeSync + 'static,
+        {
+            #[inline(always)]
+            fn into_embedded_fn(self) -> FnType<T> {
+                FnType::EmbeddedRuntimeFn(
+                    EmbeddedRuntimeFn {
+                        runner: Arc::new(move |runtime, scope, mut _input| {
+                            let mut _iter = _input.into_iter();
+
+                            $(
+                                #[allow(non_snake_case)]
+                                let $ident = $ident::map(&mut _iter.next().unwrap()).ok_or_else(unreachable)?;
+                            )*
+
+                            self(runtime, scope, $($ident,)*).into_fn_result()
+                        }),
+                    }
+                )
+            }
+        }
+
+        impl<T, $($ident,)* R, O, F> IntoFnParameters<(&Scope<T>, $(&$ident,)*), (R, O), EmbRuntimeFn> for F
+        where
+            $($ident: EmbeddedFnParameter<$ident> + 'static,)*
+            R: IntoFnResult<O>,
+            F: Fn(&mut Runtime<T>, &mut Scope<T>, $($ident,)*) -> R + 'static,
+        {
+            #[inline(always)]
+            fn fn_parameters() -> Vec<UnionType> {
+                vec![$($ident::union_type(),)*]
+            }
+        }
+    };
+}
+
+def_register!();
+def_register!(A);
+def_register!(A, B);
+def_register!(A, B, C);
+def_register!(A, B, C, D);
+def_register!(A, B, C, D, E);
+def_register!(A, B, C, D, E, G);
+def_register!(A, B, C, D, E, G, H);
+def_register!(A, B, C, D, E, G, H, I);
@@ -1,48 +1,313 @@
+use crate::ast::*;
+use crate::cancellation::*;
 use crate::error::*;
 use crate::fn_storage::*;
 use crate::function::*;
+use crate::iron_std::regex::RegexCache;
+use crate::json::*;
+use crate::limits::*;
+use crate::module::*;
+use crate::optimize::*;
+use crate::resolve::*;
+use crate::rng::*;
 use crate::runtime::*;
 use crate::scope::*;
+use crate::shared::*;
+use crate::symbol::*;
 use crate::variant::*;
+use crate::vm::*;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 pub struct Engine<T> {
-    scope: Scope<T>,
+    /// Shared by every run instead of copied into it.
+    module: Arc<Module<T>>,
+    seed: Option<u64>,
+    regex_cache: RegexCache,
+    limits: Limits,
+    cancellation_token: Option<CancellationToken>,
+    on_progress: Option<ProgressFn>,
+    backend: Backend,
+    optimization_level: OptimizationLevel,
 }
 
 impl<T> Engine<T> {
     pub fn new() -> Self {
-        let mut scope = Scope::new();
+        let mut module = Module::new();
 
         let std = crate::iron_std::iron_std();
         let global = crate::iron_std::global_full();
 
-        scope.register_module("std", std);
-        scope.merge_module(global);
+        module.register_sub_module("std", std);
+        module.merge_module(global);
 
-        Self { scope }
+        Self {
+            module: Arc::new(module),
+            seed: None,
+            regex_cache: RegexCache::default(),
+            limits: Limits::default(),
+            cancellation_token: None,
+            on_progress: None,
+            backend: Backend::default(),
+            optimization_level: OptimizationLevel::default(),
+        }
     }
 
-    pub fn register_fn<P, R, F, U>(&mut self, ident: impl Into<String>, f: F) -> &mut Self
+    pub fn register_fn<P, R, F, U>(&mut self, ident: impl Into<Symbol>, f: F) -> &mut Self
     where
         F: IntoEmbeddedFn<T, P, R, U>,
         F: IntoFnParameters<P, R, U>,
     {
-        self.scope
-            .register_fn(FnSignature::from(ident, &f), f.into_embedded_fn())
+        Arc::make_mut(&mut self.module)
+            .register_fn_raw(FnSignature::from(ident, &f), f.into_embedded_fn())
             .unwrap();
 
         self
     }
 
+    /// Sets how values of type `V` are shown by `{}` in `format` and by `to_string`,
+    /// instead of `variant<type_name>`.
+    pub fn register_display<V, F>(&mut self, f: F) -> &mut Self
+    where
+        V: Variant + Clone,
+        F: Fn(&V) -> String + MaybeSync + 'static,
+    {
+        self.register_fn("display", move |value: V| f(&value))
+    }
+
+    /// Sets how values of type `V` are shown by `{:?}` in `format`, falls back to the
+    /// `display` hook when not set.
+    pub fn register_debug<V, F>(&mut self, f: F) -> &mut Self
+    where
+        V: Variant + Clone,
+        F: Fn(&V) -> String + MaybeSync + 'static,
+    {
+        self.register_fn("debug", move |value: V| f(&value))
+    }
+
+    /// Removes every overload of the function at `path`, eg. `std::sys::exit`, making
+    /// it unavailable to scripts run by this engine.
+    pub fn disable_fn(&mut self, path: &str) -> &mut Self {
+        Arc::make_mut(&mut self.module).remove_fn(path);
+
+        self
+    }
+
+    /// Seeds `std::rand` for every following run, making them reproducible. Without a
+    /// seed each run is seeded from the system clock.
+    pub fn set_seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = Some(seed);
+
+        self
+    }
+
+    /// Caps every following run, eg. so `while true {}` errors instead of hanging.
+    pub fn set_limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+
+        self
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Makes every following run stop with [`ErrorKind::Cancelled`] once `token` is
+    /// cancelled.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) -> &mut Self {
+        self.cancellation_token = Some(token);
+
+        self
+    }
+
+    /// Calls `f` every [`PROGRESS_INTERVAL`] operations with the number of operations
+    /// so far, the run stops with [`ErrorKind::Cancelled`] once it returns `false`.
+    pub fn on_progress<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(u64) -> bool + MaybeSync + 'static,
+    {
+        self.on_progress = Some(Arc::new(f));
+
+        self
+    }
+
+    /// Chooses between walking the syntax tree and compiling it for the VM.
+    pub fn set_backend(&mut self, backend: Backend) -> &mut Self {
+        self.backend = backend;
+
+        self
+    }
+
+    /// Sets how much scripts are optimized when compiled, see [`OptimizationLevel`].
+    pub fn set_optimization_level(&mut self, level: OptimizationLevel) -> &mut Self {
+        self.optimization_level = level;
+
+        self
+    }
+
+    /// Patterns compiled by scripts run with this engine.
+    pub fn regex_cache(&self) -> &RegexCache {
+        &self.regex_cache
+    }
+
+    /// Converts a JSON tree from the host into a script value, eg. to pass a request
+    /// body into a script function.
+    pub fn json_to_union(&self, json: Json) -> Union {
+        json.into_union()
+    }
+
+    /// Converts a script value back into a JSON tree.
+    pub fn union_to_json(&self, union: &Union) -> Result<Json, Error> {
+        Json::from_union(union)
+    }
+
     pub fn eval_file(&self, ctx: &mut T, path: impl Into<PathBuf>) -> Result<Union, Error> {
         let source = std::fs::read_to_string(path.into()).unwrap();
 
-        let program = crate::grammar::BlockParser::new().parse(&source).unwrap();
+        self.eval(ctx, source)
+    }
+
+    pub fn eval(&self, ctx: &mut T, source: impl Into<String>) -> Result<Union, Error> {
+        self.run(ctx, &self.compile(source)?)
+    }
+
+    /// Parses and resolves `source` once, so it can be run any number of times.
+    pub fn compile(&self, source: impl Into<String>) -> Result<Script, Error> {
+        let source = source.into();
+
+        let mut program = crate::grammar::BlockParser::new()
+            .parse(&source)
+            .map_err(|err| Error::from_parse(&source, err))?;
+        optimize(&mut program, &self.module, self.optimization_level);
+        resolve(&mut program);
+        let chunk = Chunk::compile(&program, &self.module);
+
+        Ok(Script {
+            source,
+            program,
+            chunk,
+        })
+    }
+
+    pub fn run(&self, ctx: &mut T, script: &Script) -> Result<Union, Error> {
+        let mut runtime = Runtime::new(ctx, script.source.clone());
+
+        runtime.regex_cache = self.regex_cache.clone();
+        runtime.limits = self.limits;
+        runtime.cancellation_token = self.cancellation_token.clone();
+        runtime.on_progress = self.on_progress.clone();
+        runtime.backend = self.backend;
+
+        if let Some(seed) = self.seed {
+            runtime.rng = Rng::new(seed);
+        }
+        let mut scope = Scope::with_globals(self.module.clone());
+
+        runtime.run_compiled(&script.program, Some(&script.chunk), &mut scope)
+    }
+}
+
+/// A script compiled by [`Engine::compile`].
+pub struct Script {
+    source: String,
+    program: Block,
+    /// The program compiled for [`Backend::Vm`], once instead of on every run.
+    chunk: Chunk,
+}
+
+impl Script {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compile_once_run_many() {
+        let engine = Engine::<i32>::new();
+        let script = engine.compile("let a = 20; a + 1").unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(engine.run(&mut 0, &script).unwrap().to_string(), "21");
+        }
+    }
+
+    #[test]
+    fn invalid_source() {
+        let engine = Engine::<()>::new();
+
+        for source in [
+            "fn f(x) { if x > 3 { return x; } }",
+            "try { 1 } catch { 2 }",
+            "let = 1;",
+            "[1, 2",
+            "1 $ 2",
+            "99999999999",
+        ] {
+            let err = engine.compile(source).err().unwrap();
+            assert!(matches!(err.kind, ErrorKind::InvalidSyntax), "{}", source);
+
+            assert!(engine.eval(&mut (), source).is_err());
+        }
+
+        assert_eq!(engine.compile("let = 1;").err().unwrap().code, "=");
+    }
+
+    #[test]
+    fn runs_share_functions() {
+        let mut engine = Engine::<()>::new();
+        engine.register_fn("double", |x: i32| x * 2);
+
+        let module = engine.module.clone();
+        let eval = |source: &str| engine.eval(&mut (), source).map(|u| u.to_string());
+
+        assert_eq!(
+            eval("fn double(x: f32) { x } format(\"{} {}\", double(2), double(1.5))").unwrap(),
+            "4 1.5"
+        );
+        assert!(eval("double(1.5)").is_err());
+        assert!(eval("fn double(x: i32) { x }").is_err());
+
+        // overloads are picked as if script functions were registered with the host's
+        assert_eq!(
+            eval("fn +(a, b) { \"script\" } format(\"{} {}\", \"a\" + 1, true + 1)").unwrap(),
+            "a1 script"
+        );
+
+        assert!(Arc::ptr_eq(&module, &engine.module));
+    }
+
+    #[cfg(all(feature = "sync", not(feature = "unsync")))]
+    #[test]
+    fn run_in_parallel() {
+        fn assert_send_sync<S: Send + Sync>() {}
+
+        assert_send_sync::<Engine<()>>();
+        assert_send_sync::<Script>();
+        assert_send_sync::<crate::module::Module<()>>();
+
+        let mut engine = Engine::<i32>::new();
+        engine.register_fn("double", |x: i32| x * 2);
+        engine.on_progress(|_| true);
+
+        let script = engine
+            .compile(
+                "fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } } \
+             let a = arr(); for i in 0..10 { a.push(double(i)); } fib(15) + a.len()",
+            )
+            .unwrap();
 
-        let mut runtime = Runtime::new(ctx, source);
-        let mut scope = self.scope.clone();
+        std::thread::scope(|threads| {
+            let runs: Vec<_> = (0..8)
+                .map(|_| threads.spawn(|| engine.run(&mut 0, &script).unwrap().to_string()))
+                .collect();
 
-        runtime.run(&program, &mut scope)
+            for run in runs {
+                assert_eq!(run.join().unwrap(), "620");
+            }
+        });
     }
 }
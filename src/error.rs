@@ -1,4 +1,5 @@
 use crate::span::*;
+use lalrpop_util::ParseError;
 
 #[derive(Debug)]
 pub enum ErrorKind {
@@ -8,6 +9,17 @@ pub enum ErrorKind {
     Unreachable,
     FunctionRedefinition,
     InvalidDerefTarget,
+    IndexOutOfBounds,
+    InvalidArgument,
+    InvalidSyntax,
+    Unwrap,
+    TooManyOperations,
+    CallDepthExceeded,
+    StringTooLong,
+    ArrayTooLong,
+    TooManyVariables,
+    Cancelled,
+    Exit(i32),
 }
 
 #[derive(Debug)]
@@ -29,4 +41,33 @@ impl Error {
             code: code.into(),
         }
     }
+
+    /// Turns what the parser rejected into an [`ErrorKind::InvalidSyntax`] error.
+    pub(crate) fn from_parse<T>(source: &str, err: ParseError<usize, T, &'static str>) -> Self {
+        let span = match err {
+            ParseError::InvalidToken { location } => {
+                let len = source[location..].chars().next().map_or(0, char::len_utf8);
+
+                Span::new(location, location + len)
+            }
+            ParseError::UnrecognizedToken {
+                token: (lo, _, hi), ..
+            }
+            | ParseError::ExtraToken { token: (lo, _, hi) } => Span::new(lo, hi),
+            ParseError::UnrecognizedEOF { .. } => {
+                return Self::from_raw(ErrorKind::InvalidSyntax, "unexpected end of source")
+            }
+            ParseError::User { error } => return Self::from_raw(ErrorKind::InvalidSyntax, error),
+        };
+
+        Self::new(ErrorKind::InvalidSyntax, source, span)
+    }
 }
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.code)
+    }
+}
+
+impl std::error::Error for Error {}
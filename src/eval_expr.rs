@@ -1,10 +1,16 @@
 use crate::ast::*;
+use crate::call_cache::*;
+use crate::closure::*;
 use crate::control_flow::*;
 use crate::error::*;
 use crate::fn_storage::*;
+use crate::function::*;
+use crate::iron_std::option::ScriptResult;
+use crate::range::*;
 use crate::runtime::*;
 use crate::scope::*;
 use crate::span::*;
+use crate::symbol::*;
 use crate::to_fn_input::*;
 use crate::variant::*;
 
@@ -15,141 +21,87 @@ impl<'a, T> Runtime<'a, T> {
         expr: &Spanned<Expr>,
         scope: &mut Scope<T>,
     ) -> Result<Variable, ControlFlow> {
+        self.count_operation()?;
+
         match &**expr {
             Expr::Literal { variant } => Ok(Variable::unspecified(variant.clone())),
 
-            Expr::Assign { target, variable } => {
-                let mut target = self.eval_expr(target, scope)?;
-                let variant = self.eval_expr(variable, scope)?;
-
-                if target.type_specified {
-                    if target.ty() != variant.ty() {
-                        return Err(
-                            Error::new(ErrorKind::TypeMismatch, &self.source, expr.span).into()
-                        );
-                    }
-                }
+            Expr::Constant { variant } => Ok(Variable::specified(variant.clone())),
 
-                target.set(variant.into_inner());
+            Expr::Array { items } => {
+                let mut array = Vec::with_capacity(items.len());
 
-                Ok(Variable::specified(UnionCell::new(())))
-            }
-
-            Expr::NegationOp { expr } => {
-                let variable = self.eval_expr(expr, scope)?;
+                for item in items {
+                    array.push(UnionCell::Owned(self.eval_expr(item, scope)?.into_inner()));
+                }
 
-                let params = vec![variable.clone()];
+                Ok(Variable::unspecified(Union::from(array)))
+            }
 
-                let fn_signature = FnSignature {
-                    ident: "!".into(),
-                    params: params.to_fn_parameters(),
+            Expr::Closure {
+                parameter_idents,
+                body,
+            } => {
+                let closure = Closure {
+                    parameter_idents: parameter_idents.clone(),
+                    body: body.clone(),
+                    captured: scope.capture(),
+                    chunk: None,
                 };
 
-                match scope.get_fn(&fn_signature) {
-                    Ok(op_fn) => {
-                        Ok(op_fn
-                            .clone()
-                            .run(&expr.span, self, scope, params.to_fn_input())?)
-                    }
-                    Err(_) => variable.map(|u| match u {
-                        Union::Bool(b) => Ok(Variable::specified(Union::Bool(!b))),
-                        _ => Err(Error::from_raw(ErrorKind::UndefinedFunction, "!").into()),
-                    }),
-                }
+                Ok(Variable::unspecified(Union::from(closure)))
             }
 
-            Expr::BinOp { lhs, rhs, op } => {
-                let lhs = self.eval_expr(lhs, scope)?;
-                let rhs = self.eval_expr(rhs, scope)?;
+            Expr::Assign { target, variable } => {
+                let target = self.eval_expr(target, scope)?;
+                let variable = self.eval_expr(variable, scope)?;
 
-                let fn_signature = FnSignature {
-                    ident: op.inner.clone(),
-                    params: vec![lhs.ty(), rhs.ty()],
-                };
+                self.assign(expr.span, target, variable)
+            }
 
-                match scope.get_fn(&fn_signature) {
-                    Ok(op_fn) => {
-                        let params = vec![lhs, rhs];
+            Expr::NegationOp { expr } => {
+                let variable = self.eval_expr(expr, scope)?;
 
-                        Ok(op_fn
-                            .clone()
-                            .run(&op.span, self, scope, params.to_fn_input())?)
-                    }
-                    Err(_) => {
-                        match crate::internal_binop::internal_binop(
-                            lhs.into_inner(),
-                            rhs.into_inner(),
-                            op,
-                        ) {
-                            Some(v) => Ok(Variable::specified(v)),
-                            None => {
-                                Err(
-                                    Error::new(ErrorKind::UndefinedFunction, &self.source, op.span)
-                                        .into(),
-                                )
-                            }
-                        }
-                    }
-                }
+                self.negate(expr.span, variable, scope)
             }
 
-            Expr::Variable { ident } => {
-                let variable = scope.get_variable_mut(ident);
+            Expr::BinOp { lhs, rhs, op, site } => {
+                let lhs = self.eval_expr(lhs, scope)?;
+                let rhs = self.eval_expr(rhs, scope)?;
 
-                match variable {
-                    Some(v) => Ok(v.get_shared()),
-                    None => Err(
-                        Error::new(ErrorKind::UndefinedVariable, &self.source, ident.span).into(),
-                    ),
-                }
+                self.call_binop(op, *site, lhs, rhs, scope)
             }
 
-            Expr::Reference { expr } => {
-                let mut variable = self.eval_expr(expr, scope)?;
+            Expr::Variable { ident, slot } => self.load_variable(ident, *slot, scope),
 
-                let referenced = Variable {
-                    type_specified: variable.type_specified,
-                    union: Union::Reference(Box::new(variable.get_shared())).into(),
-                };
+            Expr::Reference { expr } => {
+                let variable = self.eval_expr(expr, scope)?;
 
-                Ok(referenced)
+                Ok(reference(variable))
             }
 
             Expr::Dereference { expr } => {
                 let variable = self.eval_expr(expr, scope)?;
 
-                if let Union::Reference(mut referenced) = variable.into_inner() {
-                    Ok(referenced.get_shared())
-                } else {
-                    Err(Error::new(ErrorKind::InvalidDerefTarget, &self.source, expr.span).into())
-                }
+                self.dereference(expr.span, variable)
             }
 
-            Expr::Block { block } => {
-                scope.sub(false);
-
-                let ret = self.eval_block(block, scope);
-
-                scope.rev_sub();
+            Expr::Try { expr } => {
+                let variable = self.eval_expr(expr, scope)?;
 
-                ret
+                self.try_unwrap(expr.span, variable)
             }
 
+            Expr::Block { block } => self.eval_sub_block(block, scope),
+
             Expr::If {
                 check,
                 block,
                 else_block,
             } => {
-                let check = self.eval_expr(check, scope)?.map(|v| match v.as_bool() {
-                    Some(b) => Ok(b),
-                    None => Err(Error::new(
-                        ErrorKind::TypeMismatch,
-                        &self.source,
-                        check.span,
-                    )),
-                })?;
-
-                if check {
+                let variable = self.eval_expr(check, scope)?;
+
+                if self.check(check.span, &variable)? {
                     self.eval_block(block, scope)
                 } else {
                     if let Some(else_block) = else_block {
@@ -160,7 +112,12 @@ impl<'a, T> Runtime<'a, T> {
                 }
             }
 
-            Expr::FnCall { ident, params } => {
+            Expr::FnCall {
+                ident,
+                params,
+                slot,
+                site,
+            } => {
                 let params = {
                     let mut p = Vec::with_capacity(params.len());
 
@@ -171,13 +128,14 @@ impl<'a, T> Runtime<'a, T> {
                     p
                 };
 
-                Ok(self.call_fn(&**ident, params, scope)?)
+                self.call_named(ident, *slot, *site, params, scope)
             }
 
             Expr::MethodCall {
                 ident,
                 caller,
                 params,
+                site,
             } => {
                 let caller = self.eval_expr(caller, scope)?;
 
@@ -189,21 +147,7 @@ impl<'a, T> Runtime<'a, T> {
                     p.push(self.eval_expr(param, scope)?);
                 }
 
-                match self.call_fn(&**ident, p.clone(), scope) {
-                    Ok(v) => Ok(v),
-                    Err(Error {
-                        kind: ErrorKind::UndefinedFunction,
-                        ..
-                    }) => {
-                        p[0] = Variable::new(
-                            Union::Reference(Box::new(p[0].get_shared())),
-                            p[0].type_specified,
-                        );
-
-                        Ok(self.call_fn(&**ident, p, scope)?)
-                    }
-                    Err(err) => Err(err.into()),
-                }
+                self.call_method(ident, *site, p, scope)
             }
 
             Expr::TryCatch {
@@ -211,22 +155,19 @@ impl<'a, T> Runtime<'a, T> {
                 catch_block,
             } => match self.eval_block(try_block, scope) {
                 Ok(_) => Ok(Variable::specified(Union::Unit(()))),
-                Err(_) => self.eval_block(catch_block, scope),
+                Err(ControlFlow::Error(_)) => self.eval_block(catch_block, scope),
+                // returns, including `?`, and exits leave the function past the `try`
+                Err(flow) => Err(flow),
             },
 
             Expr::WhileLoop { expr, block } => {
                 loop {
-                    let check = self.eval_expr(expr, scope)?.map(|v| match v.as_bool() {
-                        Some(b) => Ok(b),
-                        None => Err(Error::new(ErrorKind::TypeMismatch, &self.source, expr.span)),
-                    })?;
+                    let variable = self.eval_expr(expr, scope)?;
 
-                    if check {
-                        scope.sub(false);
-
-                        self.eval_block(block, scope)?;
+                    if self.check(expr.span, &variable)? {
+                        self.check_cancelled()?;
 
-                        scope.rev_sub();
+                        self.eval_sub_block(block, scope)?;
                     } else {
                         break;
                     }
@@ -236,59 +177,454 @@ impl<'a, T> Runtime<'a, T> {
             }
 
             Expr::ForLoop { ident, expr, block } => {
-                let iterator = self.eval_expr(expr, scope)?;
-
-                // try to turn into iter
-                let mut iterator = match self.call_fn("into_iter", vec![iterator.clone()], scope) {
-                    Ok(iterator) => iterator,
-                    Err(Error {
-                        kind: ErrorKind::UndefinedFunction,
-                        ..
-                    }) => iterator,
-                    Err(err) => return Err(err.into()),
+                let iterable = self.eval_expr(expr, scope)?;
+                let iteration = self.start_iteration(ident, iterable, scope)?;
+
+                while let Some(union) = self.next_item(&iteration, expr.span, scope)? {
+                    scope.sub(false);
+
+                    scope.push(ident.inner, Variable::unspecified(union));
+
+                    let ret = self.eval_block(block, scope);
+
+                    scope.rev_sub();
+
+                    ret?;
+                }
+
+                Ok(Variable::unspecified(Union::Unit(())))
+            }
+
+            Expr::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let mut bound = |bound: &Option<Box<Spanned<Expr>>>| match bound {
+                    Some(expr) => {
+                        let variable = self.eval_expr(expr, scope)?;
+
+                        self.range_bound(expr.span, &variable).map(Some)
+                    }
+                    None => Ok(None),
+                };
+
+                let range = Range {
+                    start: bound(start)?,
+                    end: bound(end)?,
+                    inclusive: *inclusive,
+                };
+
+                Ok(Variable::unspecified(Union::Range(range)))
+            }
+
+            Expr::Match { expr, arms } => {
+                let value = self.eval_expr(expr, scope)?;
+
+                for arm in arms {
+                    if self.matches_pattern(&arm.pattern, &value, scope)? {
+                        scope.sub(false);
+
+                        if let Pattern::Binding(ident) = &*arm.pattern {
+                            scope.push(*ident, value);
+                        }
+
+                        let ret = self.eval_expr(&arm.expr, scope);
+
+                        scope.rev_sub();
+
+                        return ret;
+                    }
+                }
+
+                Ok(Variable::unspecified(Union::Unit(())))
+            }
+        }
+    }
+
+    /// Runs `block` in a sub scope of its own, which is closed again even if it fails.
+    pub(crate) fn eval_sub_block(
+        &mut self,
+        block: &Block,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        scope.sub(false);
+
+        let ret = self.eval_block(block, scope);
+
+        scope.rev_sub();
+
+        ret
+    }
+
+    /// Sets `target` to `variable`, a target declared with a type only accepts values of
+    /// that type.
+    pub(crate) fn assign(
+        &mut self,
+        span: Span,
+        mut target: Variable,
+        variable: Variable,
+    ) -> Result<Variable, ControlFlow> {
+        if target.type_specified && target.ty() != variable.ty() {
+            return Err(Error::new(ErrorKind::TypeMismatch, &self.source, span).into());
+        }
+
+        target.set(variable.into_inner());
+
+        Ok(Variable::specified(UnionCell::new(())))
+    }
+
+    /// `lhs op rhs` at `site`.
+    pub(crate) fn call_binop(
+        &mut self,
+        op: &Spanned<Symbol>,
+        site: CallSite,
+        lhs: Variable,
+        rhs: Variable,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        let params = [lhs, rhs];
+
+        let op_fn = match self.cached_call(site, op.inner, &params, scope) {
+            Some(target) => target.fn_type,
+            None => {
+                let fn_signature = FnSignature {
+                    ident: op.inner,
+                    params: vec![params[0].ty(), params[1].ty()],
                 };
 
-                let params = vec![Variable::specified(Union::Reference(Box::new(
-                    iterator.get_shared(),
-                )))];
+                let op_fn = scope.get_fn(&fn_signature).ok().cloned();
+
+                let target = CallTarget {
+                    fn_type: op_fn.clone(),
+                    by_reference: false,
+                };
+                self.cache_call(site, op.inner, fn_signature.params, scope, target);
+
+                op_fn
+            }
+        };
+
+        let [lhs, rhs] = params;
+
+        self.apply_binop(op_fn, op.as_str(), op.span, lhs, rhs, scope)
+    }
+
+    /// `!variable`, preferring a registered `!` over negating a `bool`.
+    pub(crate) fn negate(
+        &mut self,
+        span: Span,
+        variable: Variable,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        let params = vec![variable.clone()];
+
+        let fn_signature = FnSignature {
+            ident: "!".into(),
+            params: params.to_fn_parameters(),
+        };
+
+        match scope.get_fn(&fn_signature) {
+            Ok(op_fn) => Ok(op_fn
+                .clone()
+                .run(&span, self, scope, params.to_fn_input())?),
+            Err(_) => variable.map(|u| match u {
+                Union::Bool(b) => Ok(Variable::specified(Union::Bool(!b))),
+                _ => Err(Error::from_raw(ErrorKind::UndefinedFunction, "!").into()),
+            }),
+        }
+    }
 
+    pub(crate) fn load_variable(
+        &mut self,
+        ident: &Spanned<Symbol>,
+        slot: Option<Slot>,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        match slot {
+            Some(slot) => Ok(scope.get_mut(slot).get_shared()),
+            // module constants, eg. `std::math::PI`, are functions without parameters
+            None if ident.contains("::") => {
                 let fn_signature = FnSignature {
-                    ident: "iter_next".into(),
-                    params: params.to_fn_parameters(),
+                    ident: ident.inner,
+                    params: Vec::new(),
                 };
 
-                let iter_next = scope
+                match scope.get_fn(&fn_signature) {
+                    Ok(fn_type) => fn_type.clone().run(&ident.span, self, scope, Vec::new()),
+                    Err(_) => {
+                        Err(
+                            Error::new(ErrorKind::UndefinedVariable, &self.source, ident.span)
+                                .into(),
+                        )
+                    }
+                }
+            }
+            // declared in an `if` or `try` block, which may not have run
+            None => match scope.find(ident.inner) {
+                Some(variable) => Ok(variable.get_shared()),
+                None => {
+                    Err(Error::new(ErrorKind::UndefinedVariable, &self.source, ident.span).into())
+                }
+            },
+        }
+    }
+
+    pub(crate) fn dereference(
+        &mut self,
+        span: Span,
+        variable: Variable,
+    ) -> Result<Variable, ControlFlow> {
+        if let Union::Reference(mut referenced) = variable.into_inner() {
+            Ok(referenced.get_shared())
+        } else {
+            Err(Error::new(ErrorKind::InvalidDerefTarget, &self.source, span).into())
+        }
+    }
+
+    /// `variable?`
+    pub(crate) fn try_unwrap(
+        &mut self,
+        span: Span,
+        variable: Variable,
+    ) -> Result<Variable, ControlFlow> {
+        let value = variable.into_inner();
+
+        // `none` and `err(..)` are returned as they are
+        if let Some(option) = value.downcast_ref::<Option<Union>>() {
+            return match option {
+                Some(inner) => Ok(Variable::unspecified(inner.clone())),
+                None => Err(ControlFlow::Return(Variable::unspecified(value))),
+            };
+        }
+
+        if let Some(result) = value.downcast_ref::<ScriptResult>() {
+            return match result {
+                Ok(inner) => Ok(Variable::unspecified(inner.clone())),
+                Err(_) => Err(ControlFlow::Return(Variable::unspecified(value))),
+            };
+        }
+
+        Err(Error::new(ErrorKind::TypeMismatch, &self.source, span).into())
+    }
+
+    /// The condition of an `if` or `while`.
+    pub(crate) fn check(&self, span: Span, variable: &Variable) -> Result<bool, ControlFlow> {
+        variable.map(|v| match v.as_bool() {
+            Some(b) => Ok(b),
+            None => Err(Error::new(ErrorKind::TypeMismatch, &self.source, span).into()),
+        })
+    }
+
+    pub(crate) fn call_named(
+        &mut self,
+        ident: &Spanned<Symbol>,
+        slot: Option<Slot>,
+        site: CallSite,
+        params: Vec<Variable>,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        if let Some(CallTarget {
+            fn_type: Some(fn_type),
+            ..
+        }) = self.cached_call(site, ident.inner, &params, scope)
+        {
+            return fn_type.run(&ident.span, self, scope, params);
+        }
+
+        let fn_signature = FnSignature {
+            ident: ident.inner,
+            params: params.to_fn_parameters(),
+        };
+
+        match scope.get_fn(&fn_signature) {
+            Ok(fn_type) => {
+                let fn_type = fn_type.clone();
+
+                let target = CallTarget {
+                    fn_type: Some(fn_type.clone()),
+                    by_reference: false,
+                };
+                self.cache_call(site, ident.inner, fn_signature.params, scope, target);
+
+                fn_type.run(&ident.span, self, scope, params)
+            }
+            Err(err) => {
+                // not a function, but it might be a variable holding a closure
+                let variable = match slot {
+                    Some(slot) => Some(scope.get(slot)),
+                    None => scope.find(ident.inner).map(|variable| &*variable),
+                };
+                let closure = variable
+                    .and_then(|variable| variable.map(|u| u.downcast_ref::<Closure>().cloned()));
+
+                match closure {
+                    Some(closure) => self.call_closure(&closure, params, scope),
+                    None => Err(Error::from_raw(
+                        err,
+                        format!("{} {:?}", fn_signature.ident, fn_signature.params),
+                    )
+                    .into()),
+                }
+            }
+        }
+    }
+
+    /// Calls the method `ident` with the caller as the first of `params`.
+    pub(crate) fn call_method(
+        &mut self,
+        ident: &Spanned<Symbol>,
+        site: CallSite,
+        mut params: Vec<Variable>,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        if let Some(CallTarget {
+            fn_type: Some(fn_type),
+            by_reference,
+        }) = self.cached_call(site, ident.inner, &params, scope)
+        {
+            if by_reference {
+                caller_by_reference(&mut params);
+            }
+
+            return fn_type.run(&ident.span, self, scope, params);
+        }
+
+        let types = params.to_fn_parameters();
+
+        let mut fn_signature = FnSignature {
+            ident: ident.inner,
+            params: types.clone(),
+        };
+
+        // methods can take the caller either by value or by reference
+        let (fn_type, by_reference) = match scope.get_fn(&fn_signature) {
+            Ok(fn_type) => (fn_type.clone(), false),
+            Err(_) => {
+                caller_by_reference(&mut params);
+                fn_signature.params[0] = params[0].ty();
+
+                let fn_type = scope
                     .get_fn(&fn_signature)
                     .map_err(|err| {
-                        Error::from_raw(err, format!("{} is not an iterator", ident.inner))
+                        Error::from_raw(
+                            err,
+                            format!("{} {:?}", fn_signature.ident, fn_signature.params),
+                        )
                     })?
                     .clone();
 
-                loop {
-                    let variable =
-                        iter_next.run(&expr.span, self, scope, params.clone().to_fn_input())?;
+                (fn_type, true)
+            }
+        };
+
+        let target = CallTarget {
+            fn_type: Some(fn_type.clone()),
+            by_reference,
+        };
+        self.cache_call(site, ident.inner, types, scope, target);
+
+        fn_type.run(&ident.span, self, scope, params)
+    }
 
-                    let option = variable
-                        .into_inner()
-                        .downcast::<Option<Union>>()
-                        .ok_or(Error::new(ErrorKind::TypeMismatch, &self.source, expr.span))?;
+    /// Turns the value looped over by a `for` into an iterator.
+    pub(crate) fn start_iteration(
+        &mut self,
+        ident: &Spanned<Symbol>,
+        iterable: Variable,
+        scope: &mut Scope<T>,
+    ) -> Result<Iteration<T>, ControlFlow> {
+        let mut iterator = self.into_iter(iterable, scope)?;
 
-                    match option {
-                        Some(union) => {
-                            scope.sub(false);
+        let params = vec![Variable::specified(Union::Reference(Box::new(
+            iterator.get_shared(),
+        )))];
 
-                            scope.push(ident.inner.clone(), Variable::unspecified(union));
+        let fn_signature = FnSignature {
+            ident: "iter_next".into(),
+            params: params.to_fn_parameters(),
+        };
 
-                            self.eval_block(block, scope)?;
+        let iter_next = scope
+            .get_fn(&fn_signature)
+            .map_err(|err| Error::from_raw(err, format!("{} is not an iterator", ident.inner)))?
+            .clone();
 
-                            scope.rev_sub();
-                        }
-                        None => break,
-                    }
-                }
+        Ok(Iteration { params, iter_next })
+    }
 
-                Ok(Variable::unspecified(Union::Unit(())))
+    /// Gets the next item of a `for`, this is where loops can be cancelled.
+    pub(crate) fn next_item(
+        &mut self,
+        iteration: &Iteration<T>,
+        span: Span,
+        scope: &mut Scope<T>,
+    ) -> Result<Option<Union>, ControlFlow> {
+        self.check_cancelled()?;
+
+        let variable =
+            iteration
+                .iter_next
+                .run(&span, self, scope, iteration.params.clone().to_fn_input())?;
+
+        variable
+            .into_inner()
+            .downcast::<Option<Union>>()
+            .ok_or_else(|| Error::new(ErrorKind::TypeMismatch, &self.source, span).into())
+    }
+
+    pub(crate) fn range_bound(&self, span: Span, variable: &Variable) -> Result<i32, ControlFlow> {
+        match variable.map(Union::as_int) {
+            Some(i) => Ok(i),
+            None => Err(Error::new(ErrorKind::TypeMismatch, &self.source, span).into()),
+        }
+    }
+
+    pub(crate) fn matches_pattern(
+        &mut self,
+        pattern: &Spanned<Pattern>,
+        value: &Variable,
+        scope: &mut Scope<T>,
+    ) -> Result<bool, ControlFlow> {
+        match &**pattern {
+            Pattern::Wildcard | Pattern::Binding(_) => Ok(true),
+            // patterns of another type never match
+            Pattern::Literal(literal) if literal.ty() != value.ty() => Ok(false),
+            Pattern::Literal(literal) => {
+                let equal = self.eval_binop(
+                    "==",
+                    pattern.span,
+                    value.clone_shared(),
+                    Variable::unspecified(literal.clone()),
+                    scope,
+                )?;
+
+                Ok(equal.map(Union::as_bool) == Some(true))
+            }
+            Pattern::Range(range) => {
+                Ok(value.map(Union::as_int).is_some_and(|i| range.contains(i)))
             }
         }
     }
 }
+
+fn caller_by_reference(params: &mut [Variable]) {
+    params[0] = Variable::new(
+        Union::Reference(Box::new(params[0].get_shared())),
+        params[0].type_specified,
+    );
+}
+
+/// `&variable`
+pub(crate) fn reference(mut variable: Variable) -> Variable {
+    Variable {
+        type_specified: variable.type_specified,
+        union: Union::Reference(Box::new(variable.get_shared())).into(),
+    }
+}
+
+/// The iterator of a running `for` and the `iter_next` that advances it.
+pub(crate) struct Iteration<T> {
+    params: Vec<Variable>,
+    iter_next: FnType<T>,
+}
@@ -1,10 +1,13 @@
 use crate::ast::*;
 use crate::control_flow::*;
 use crate::error::*;
+use crate::fn_storage::*;
 use crate::function::*;
 use crate::runtime::*;
 use crate::scope::*;
 use crate::span::*;
+use crate::symbol::*;
+use crate::variant::*;
 
 impl<'a, T> Runtime<'a, T> {
     #[inline(always)]
@@ -13,21 +16,13 @@ impl<'a, T> Runtime<'a, T> {
         stmt: &Spanned<Stmt>,
         scope: &mut Scope<T>,
     ) -> Result<(), ControlFlow> {
+        self.count_operation()?;
+
         match &**stmt {
             Stmt::Let { ident, ty, expr } => {
                 let variable = self.eval_expr(expr, scope)?;
 
-                if let Some(ty) = ty {
-                    if *ty != variable.ty() {
-                        return Err(
-                            Error::new(ErrorKind::TypeMismatch, &self.source, stmt.span).into()
-                        );
-                    }
-                }
-
-                scope.push(ident.clone(), variable);
-
-                Ok(())
+                self.declare(*ident, ty.as_ref(), stmt.span, variable, scope)
             }
 
             Stmt::Expr { expr } => {
@@ -46,14 +41,45 @@ impl<'a, T> Runtime<'a, T> {
                     block: block.clone(),
                     parameter_idents: parameter_idents.clone(),
                     return_type: return_type.clone(),
+                    chunk: None,
                 };
 
-                scope
-                    .register_fn(fn_signature.clone(), fn_type)
-                    .map_err(|err| Error::new(err, &self.source, stmt.span))?;
+                self.define_fn(fn_signature, fn_type, stmt.span, scope)
+            }
+        }
+    }
 
-                Ok(())
+    /// `let ident: ty = variable;`, the identifier was resolved to the next slot.
+    pub(crate) fn declare(
+        &mut self,
+        ident: Symbol,
+        ty: Option<&UnionType>,
+        span: Span,
+        variable: Variable,
+        scope: &mut Scope<T>,
+    ) -> Result<(), ControlFlow> {
+        if let Some(ty) = ty {
+            if *ty != variable.ty() {
+                return Err(Error::new(ErrorKind::TypeMismatch, &self.source, span).into());
             }
         }
+
+        scope.push(ident, variable);
+
+        self.check_variables(scope)
+    }
+
+    pub(crate) fn define_fn(
+        &mut self,
+        fn_signature: &FnSignature,
+        fn_type: FnType<T>,
+        span: Span,
+        scope: &mut Scope<T>,
+    ) -> Result<(), ControlFlow> {
+        scope
+            .register_fn(fn_signature.clone(), fn_type)
+            .map_err(|err| Error::new(err, &self.source, span))?;
+
+        Ok(())
     }
 }
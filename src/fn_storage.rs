@@ -1,5 +1,6 @@
 use crate::error::*;
 use crate::function::*;
+use crate::symbol::*;
 use crate::variant::*;
 use fnv::FnvHashMap;
 use std::any::{Any, TypeId};
@@ -7,12 +8,12 @@ use std::collections::HashMap;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct FnSignature {
-    pub ident: String,
+    pub ident: Symbol,
     pub params: Vec<UnionType>,
 }
 
 impl FnSignature {
-    pub fn from<F, P, R, U>(ident: impl Into<String>, f: &F) -> Self
+    pub fn from<F, P, R, U>(ident: impl Into<Symbol>, f: &F) -> Self
     where
         F: IntoFnParameters<P, R, U>,
     {
@@ -55,7 +56,10 @@ impl<T> FnStorageBranch<T> {
             }
         }
 
-        self.end = branch.end;
+        // a branch without its own end mustn't erase ours
+        if branch.end.is_some() {
+            self.end = branch.end;
+        }
     }
 
     #[inline(always)]
@@ -99,6 +103,41 @@ impl<T> FnStorageBranch<T> {
             },
         }
     }
+
+    /// Whether a function was registered for exactly these parameters, unlike
+    /// [`FnStorageBranch::get_fn`] an `any` parameter doesn't match other types.
+    pub fn contains<'a, I: Iterator<Item = &'a UnionType>>(&self, mut iter: I) -> bool {
+        match iter.next() {
+            Some(p) => match self.branches.get(p) {
+                Some(b) => b.contains(iter),
+                None => false,
+            },
+            None => self.end.is_some(),
+        }
+    }
+}
+
+/// Looks a function up in several trees as if they were merged into one, a function
+/// registered in both is taken from the first.
+fn get_fn_merged<'a, T>(
+    mut branches: [Option<&'a FnStorageBranch<T>>; 2],
+    params: &[UnionType],
+) -> Result<&'a FnType<T>, ErrorKind> {
+    for p in params {
+        let exact = branches.map(|b| b.and_then(|b| b.branches.get(p)));
+
+        branches = if exact.iter().any(Option::is_some) {
+            exact
+        } else {
+            branches.map(|b| b.and_then(|b| b.branches.get(&UnionType::Any)))
+        };
+    }
+
+    branches
+        .iter()
+        .flatten()
+        .find_map(|b| b.end.as_ref())
+        .ok_or(ErrorKind::UndefinedFunction)
 }
 
 impl<T> Clone for FnStorageBranch<T> {
@@ -112,7 +151,7 @@ impl<T> Clone for FnStorageBranch<T> {
 
 #[derive(Debug)]
 pub struct FnStorage<T> {
-    functions: FnvHashMap<String, FnStorageBranch<T>>,
+    functions: FnvHashMap<Symbol, FnStorageBranch<T>>,
 }
 
 impl<T> FnStorage<T> {
@@ -145,10 +184,46 @@ impl<T> FnStorage<T> {
             .register_fn(fn_signature.params.into_iter(), fn_type)
     }
 
+    /// Removes every overload of the function named `ident`, returns whether any existed.
+    pub fn remove_fn(&mut self, ident: &str) -> bool {
+        match Symbol::get(ident) {
+            Some(ident) => self.functions.remove(&ident).is_some(),
+            None => false,
+        }
+    }
+
+    /// Whether a function was registered for exactly `fn_signature`.
+    pub fn contains(&self, fn_signature: &FnSignature) -> bool {
+        match self.functions.get(&fn_signature.ident) {
+            Some(b) => b.contains(fn_signature.params.iter()),
+            None => false,
+        }
+    }
+
     #[inline(always)]
     pub fn get_fn(&self, fn_signature: &FnSignature) -> Result<&FnType<T>, ErrorKind> {
-        match self.functions.get(&fn_signature.ident) {
-            Some(b) => b.get_fn(fn_signature.params.iter()),
+        self.get_fn_raw(fn_signature.ident, &fn_signature.params)
+    }
+
+    /// Gets a function from `self` or `base` as if `self` was merged into `base`,
+    /// without merging them.
+    #[inline(always)]
+    pub fn get_fn_over<'a>(
+        &'a self,
+        base: &'a FnStorage<T>,
+        ident: Symbol,
+        params: &[UnionType],
+    ) -> Result<&'a FnType<T>, ErrorKind> {
+        get_fn_merged(
+            [self.functions.get(&ident), base.functions.get(&ident)],
+            params,
+        )
+    }
+
+    #[inline(always)]
+    pub fn get_fn_raw(&self, ident: Symbol, params: &[UnionType]) -> Result<&FnType<T>, ErrorKind> {
+        match self.functions.get(&ident) {
+            Some(b) => b.get_fn(params.iter()),
             None => Err(ErrorKind::UndefinedFunction),
         }
     }
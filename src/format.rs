@@ -0,0 +1,653 @@
+use crate::closure::*;
+use crate::control_flow::*;
+use crate::error::*;
+use crate::fn_storage::*;
+use crate::iron_std::option::ScriptResult;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::shared::*;
+use crate::span::*;
+use crate::variant::*;
+
+/// Shown in place of a shared value met again while it's being formatted.
+const CYCLE: &str = "[...]";
+
+fn format_error(message: impl std::fmt::Display) -> ControlFlow {
+    Error::from_raw(ErrorKind::InvalidArgument, format!("format: {}", message)).into()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Parsed `{arg:spec}` placeholder, following Rust's format syntax.
+struct Spec<'a> {
+    arg: &'a str,
+    fill: char,
+    align: Option<Align>,
+    plus: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    ty: &'a str,
+}
+
+impl<'a> Spec<'a> {
+    fn parse(placeholder: &'a str) -> Result<Self, ControlFlow> {
+        let (arg, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+
+        let mut parsed = Self {
+            arg: arg.trim(),
+            fill: ' ',
+            align: None,
+            plus: false,
+            alternate: false,
+            zero: false,
+            width: 0,
+            precision: None,
+            ty: "",
+        };
+
+        let align = |c| match c {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        };
+
+        let mut rest = spec;
+        let mut chars = rest.chars();
+
+        match (chars.next(), chars.next().and_then(align)) {
+            (Some(fill), Some(a)) => {
+                parsed.fill = fill;
+                parsed.align = Some(a);
+                rest = &rest[fill.len_utf8() + 1..];
+            }
+            (Some(c), _) if align(c).is_some() => {
+                parsed.align = align(c);
+                rest = &rest[1..];
+            }
+            _ => {}
+        }
+
+        if let Some(stripped) = rest.strip_prefix('+') {
+            parsed.plus = true;
+            rest = stripped;
+        }
+
+        if let Some(stripped) = rest.strip_prefix('#') {
+            parsed.alternate = true;
+            rest = stripped;
+        }
+
+        if let Some(stripped) = rest.strip_prefix('0') {
+            parsed.zero = true;
+            rest = stripped;
+        }
+
+        let digits = |s: &'a str| {
+            let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            (&s[..end], &s[end..])
+        };
+
+        let (width, after_width) = digits(rest);
+        rest = after_width;
+
+        if !width.is_empty() {
+            parsed.width = width.parse().map_err(format_error)?;
+        }
+
+        if let Some(stripped) = rest.strip_prefix('.') {
+            let (precision, after_precision) = digits(stripped);
+
+            if precision.is_empty() {
+                return Err(format_error(format!(
+                    "missing precision in {{{}}}",
+                    placeholder
+                )));
+            }
+
+            parsed.precision = Some(precision.parse().map_err(format_error)?);
+            rest = after_precision;
+        }
+
+        match rest {
+            "" | "?" | "x" | "X" | "b" | "o" | "e" => parsed.ty = rest,
+            _ => {
+                return Err(format_error(format!(
+                    "unknown format spec {{{}}}",
+                    placeholder
+                )))
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// Bytes in `s` once padded, so the result can be checked before it's built.
+    fn padded_len(&self, s: &str, numeric: bool) -> usize {
+        let padding = self.width.saturating_sub(s.chars().count());
+        let fill_len = if self.zero && numeric {
+            1
+        } else {
+            self.fill.len_utf8()
+        };
+
+        s.len().saturating_add(padding.saturating_mul(fill_len))
+    }
+
+    fn pad(&self, s: String, numeric: bool) -> String {
+        let len = s.chars().count();
+
+        if len >= self.width {
+            return s;
+        }
+
+        let padding = self.width - len;
+
+        // zero padding goes between the sign and the digits
+        if self.zero && numeric {
+            let sign_len = if s.starts_with(['-', '+']) { 1 } else { 0 };
+            let (sign, digits) = s.split_at(sign_len);
+
+            return format!("{}{}{}", sign, "0".repeat(padding), digits);
+        }
+
+        let align = self
+            .align
+            .unwrap_or(if numeric { Align::Right } else { Align::Left });
+
+        let (before, after) = match align {
+            Align::Left => (0, padding),
+            Align::Center => (padding / 2, padding - padding / 2),
+            Align::Right => (padding, 0),
+        };
+
+        let fill = |n| std::iter::repeat_n(self.fill, n).collect::<String>();
+
+        format!("{}{}{}", fill(before), s, fill(after))
+    }
+}
+
+/// Most arguments `format` takes after the format string.
+pub(crate) const MAX_FORMAT_ARGS: usize = 7;
+
+/// Turns the named arguments of `fmt` for which `bind` returns `true`, like `{hp}`,
+/// into positions after the `argc` given arguments, returning the new format string
+/// and the names to pass in that order.
+///
+/// Returns [`None`] when there's nothing to bind, or when a positional argument is
+/// missing and binding would fill its place.
+pub(crate) fn bind_named_args(
+    fmt: &str,
+    argc: usize,
+    mut bind: impl FnMut(&str) -> bool,
+) -> Option<(String, Vec<String>)> {
+    let mut out = String::with_capacity(fmt.len());
+    let mut names: Vec<String> = Vec::new();
+    let mut rest = fmt;
+    let mut implicit = 0;
+
+    while let Some(i) = rest.find(['{', '}']) {
+        let brace = &rest[i..];
+
+        if brace.starts_with("{{") || brace.starts_with("}}") {
+            out.push_str(&rest[..i + 2]);
+            rest = &brace[2..];
+
+            continue;
+        }
+
+        // unmatched braces are left for `format` to report
+        let end = brace.find('}').filter(|_| brace.starts_with('{'))?;
+
+        let placeholder = &brace[1..end];
+        let (arg, spec) = match placeholder.find(':') {
+            Some(colon) => placeholder.split_at(colon),
+            None => (placeholder, ""),
+        };
+        let arg = arg.trim();
+
+        out.push_str(&rest[..i + 1]);
+
+        if arg.is_empty() {
+            implicit += 1;
+            out.push_str(placeholder);
+        } else if let Ok(index) = arg.parse::<usize>() {
+            if index >= argc {
+                return None;
+            }
+
+            out.push_str(placeholder);
+        } else if bind(arg) {
+            let position = match names.iter().position(|name| name == arg) {
+                Some(position) => position,
+                None => {
+                    names.push(arg.to_string());
+                    names.len() - 1
+                }
+            };
+
+            out.push_str(&(argc + position).to_string());
+            out.push_str(spec);
+        } else {
+            out.push_str(placeholder);
+        }
+
+        out.push('}');
+        rest = &brace[end + 1..];
+    }
+
+    out.push_str(rest);
+
+    if names.is_empty() || implicit > argc || argc + names.len() > MAX_FORMAT_ARGS {
+        None
+    } else {
+        Some((out, names))
+    }
+}
+
+impl<'a, T> Runtime<'a, T> {
+    /// Calls the `display` or `debug` hook registered for `value`'s type, if any.
+    fn format_hook(
+        &mut self,
+        hook: &str,
+        value: &Union,
+        scope: &mut Scope<T>,
+    ) -> Result<Option<String>, ControlFlow> {
+        let fn_signature = FnSignature {
+            ident: hook.into(),
+            params: vec![value.ty()],
+        };
+
+        let fn_type = match scope.get_fn(&fn_signature) {
+            Ok(fn_type) => fn_type.clone(),
+            Err(_) => return Ok(None),
+        };
+
+        let formatted = fn_type.run(
+            &Span::new(0, 0),
+            self,
+            scope,
+            vec![Variable::unspecified(value.clone())],
+        )?;
+
+        match formatted.into_inner() {
+            Union::String(s) => Ok(Some(s.to_string())),
+            other => Err(format_error(format!(
+                "`{}` returned {} instead of a string",
+                hook,
+                other.ty()
+            ))),
+        }
+    }
+
+    /// Formats `value` like `{}`, host types use their registered `display` hook and
+    /// collections are shown like `{:?}`.
+    pub fn display(&mut self, value: &Union, scope: &mut Scope<T>) -> Result<String, ControlFlow> {
+        self.display_visiting(value, &mut Vec::new(), scope)
+    }
+
+    fn display_visiting(
+        &mut self,
+        value: &Union,
+        visiting: &mut Vec<*const Lock<Union>>,
+        scope: &mut Scope<T>,
+    ) -> Result<String, ControlFlow> {
+        match value {
+            Union::Reference(variable) => {
+                let displayed =
+                    self.visit_cell(&variable.union, visiting, |runtime, union, visiting| {
+                        runtime.display_visiting(union, visiting, scope)
+                    })?;
+
+                Ok(displayed.unwrap_or_else(|| CYCLE.to_string()))
+            }
+            Union::Variant(_) => match self.format_hook("display", value, scope)? {
+                Some(s) => Ok(s),
+                None => {
+                    let mut out = String::new();
+                    self.write_debug(&mut out, value, None, visiting, scope)?;
+
+                    Ok(out)
+                }
+            },
+            _ => Ok(value.to_string()),
+        }
+    }
+
+    /// Formats `value` like `{:?}`, or `{:#?}` if `pretty`.
+    pub fn debug(
+        &mut self,
+        value: &Union,
+        pretty: bool,
+        scope: &mut Scope<T>,
+    ) -> Result<String, ControlFlow> {
+        let mut out = String::new();
+        let indent = if pretty { Some(0) } else { None };
+        self.write_debug(&mut out, value, indent, &mut Vec::new(), scope)?;
+
+        Ok(out)
+    }
+
+    /// Runs `f` on the value in `cell`. `visiting` holds the shared values currently
+    /// being formatted, meeting one of them again means a cycle and returns `None`.
+    fn visit_cell<R>(
+        &mut self,
+        cell: &UnionCell,
+        visiting: &mut Vec<*const Lock<Union>>,
+        f: impl FnOnce(&mut Self, &Union, &mut Vec<*const Lock<Union>>) -> Result<R, ControlFlow>,
+    ) -> Result<Option<R>, ControlFlow> {
+        match cell {
+            UnionCell::Owned(union) => f(self, union, visiting).map(Some),
+            UnionCell::Shared(lock) => {
+                let ptr = Shared::as_ptr(lock);
+
+                if visiting.contains(&ptr) {
+                    return Ok(None);
+                }
+
+                // formatting may call hooks, so don't hold the lock meanwhile
+                visiting.push(ptr);
+                let result = f(self, &cell.cloned(), visiting);
+                visiting.pop();
+
+                result.map(Some)
+            }
+        }
+    }
+
+    fn write_cell(
+        &mut self,
+        out: &mut String,
+        cell: &UnionCell,
+        indent: Option<usize>,
+        visiting: &mut Vec<*const Lock<Union>>,
+        scope: &mut Scope<T>,
+    ) -> Result<(), ControlFlow> {
+        let written = self.visit_cell(cell, visiting, |runtime, union, visiting| {
+            runtime.write_debug(out, union, indent, visiting, scope)
+        })?;
+
+        if written.is_none() {
+            out.push_str(CYCLE);
+        }
+
+        Ok(())
+    }
+
+    /// `indent` is the nesting depth when formatting like `{:#?}`, `None` otherwise.
+    fn write_debug(
+        &mut self,
+        out: &mut String,
+        value: &Union,
+        indent: Option<usize>,
+        visiting: &mut Vec<*const Lock<Union>>,
+        scope: &mut Scope<T>,
+    ) -> Result<(), ControlFlow> {
+        match value {
+            Union::Float(f) => out.push_str(&format!("{:?}", f)),
+            Union::String(s) => out.push_str(&format!("{:?}", s)),
+            Union::Reference(variable) => {
+                self.write_cell(out, &variable.union, indent, visiting, scope)?
+            }
+            Union::Variant(variant) => {
+                let any = Variant::as_any(&**variant);
+
+                if let Some(items) = any.downcast_ref::<Vec<UnionCell>>() {
+                    let items = items.iter().map(|item| (None, item.clone()));
+
+                    self.write_collection(out, ('[', ']'), items, indent, visiting, scope)?;
+                } else if let Some(map) = any.downcast_ref::<Map>() {
+                    let entries = map
+                        .iter()
+                        .map(|(key, value)| (Some(format!("{:?}", key)), value.clone()));
+
+                    self.write_collection(out, ('{', '}'), entries, indent, visiting, scope)?;
+                } else if let Some(option) = any.downcast_ref::<Option<Union>>() {
+                    match option {
+                        Some(inner) => {
+                            out.push_str("Some(");
+                            self.write_debug(out, inner, indent, visiting, scope)?;
+                            out.push(')');
+                        }
+                        None => out.push_str("None"),
+                    }
+                } else if let Some(result) = any.downcast_ref::<ScriptResult>() {
+                    let (name, inner) = match result {
+                        Ok(inner) => ("Ok(", inner),
+                        Err(inner) => ("Err(", inner),
+                    };
+
+                    out.push_str(name);
+                    self.write_debug(out, inner, indent, visiting, scope)?;
+                    out.push(')');
+                } else if any.is::<Closure>() {
+                    out.push_str("closure");
+                } else if let Some(s) = self.format_hook("debug", value, scope)? {
+                    out.push_str(&s);
+                } else if let Some(s) = self.format_hook("display", value, scope)? {
+                    out.push_str(&s);
+                } else {
+                    out.push_str(&value.to_string());
+                }
+            }
+            _ => out.push_str(&value.to_string()),
+        }
+
+        Ok(())
+    }
+
+    fn write_collection(
+        &mut self,
+        out: &mut String,
+        (open, close): (char, char),
+        entries: impl ExactSizeIterator<Item = (Option<String>, UnionCell)>,
+        indent: Option<usize>,
+        visiting: &mut Vec<*const Lock<Union>>,
+        scope: &mut Scope<T>,
+    ) -> Result<(), ControlFlow> {
+        out.push(open);
+
+        if entries.len() == 0 {
+            out.push(close);
+
+            return Ok(());
+        }
+
+        for (i, (key, value)) in entries.enumerate() {
+            if let Some(indent) = indent {
+                out.push('\n');
+                out.push_str(&"    ".repeat(indent + 1));
+            } else if i > 0 {
+                out.push_str(", ");
+            }
+
+            if let Some(key) = key {
+                out.push_str(&key);
+                out.push_str(": ");
+            }
+
+            self.write_cell(out, &value, indent.map(|i| i + 1), visiting, scope)?;
+
+            if indent.is_some() {
+                out.push(',');
+            }
+        }
+
+        if let Some(indent) = indent {
+            out.push('\n');
+            out.push_str(&"    ".repeat(indent));
+        }
+
+        out.push(close);
+
+        Ok(())
+    }
+
+    /// Formats `args` into `fmt`, following Rust's format syntax. Named arguments, like
+    /// `{hp}`, only work once [`bind_named_args`] turned them into positional ones.
+    pub fn format(
+        &mut self,
+        fmt: &str,
+        args: &[Union],
+        scope: &mut Scope<T>,
+    ) -> Result<String, ControlFlow> {
+        let mut out = String::with_capacity(fmt.len());
+        let mut rest = fmt;
+        let mut next_arg = 0;
+
+        while let Some(i) = rest.find(['{', '}']) {
+            out.push_str(&rest[..i]);
+
+            let brace = &rest[i..];
+
+            if brace.starts_with("{{") || brace.starts_with("}}") {
+                out.push_str(&brace[..1]);
+                rest = &brace[2..];
+
+                continue;
+            }
+
+            if brace.starts_with('}') {
+                return Err(format_error("unmatched `}`"));
+            }
+
+            let end = brace
+                .find('}')
+                .ok_or_else(|| format_error("unmatched `{`"))?;
+
+            let spec = Spec::parse(&brace[1..end])?;
+            rest = &brace[end + 1..];
+
+            let value = if spec.arg.is_empty() {
+                next_arg += 1;
+
+                args.get(next_arg - 1)
+                    .cloned()
+                    .ok_or_else(|| format_error(format!("missing argument {}", next_arg - 1)))?
+            } else if let Ok(index) = spec.arg.parse::<usize>() {
+                args.get(index)
+                    .cloned()
+                    .ok_or_else(|| format_error(format!("missing argument {}", index)))?
+            } else {
+                return Err(format_error(format!("no variable `{}`", spec.arg)));
+            };
+
+            let formatted = self.format_value(&spec, value, scope)?;
+            out.push_str(&formatted);
+        }
+
+        out.push_str(rest);
+
+        Ok(out)
+    }
+
+    fn format_value(
+        &mut self,
+        spec: &Spec,
+        value: Union,
+        scope: &mut Scope<T>,
+    ) -> Result<String, ControlFlow> {
+        let value = match value {
+            Union::Reference(variable) => variable.union.cloned(),
+            value => value,
+        };
+
+        let numeric = matches!(value, Union::Int(_) | Union::Float(_));
+
+        let formatted = match (spec.ty, &value) {
+            ("?", _) => self.debug(&value, spec.alternate, scope)?,
+            ("x", Union::Int(i)) if spec.alternate => format!("{:#x}", i),
+            ("x", Union::Int(i)) => format!("{:x}", i),
+            ("X", Union::Int(i)) if spec.alternate => format!("{:#X}", i),
+            ("X", Union::Int(i)) => format!("{:X}", i),
+            ("b", Union::Int(i)) if spec.alternate => format!("{:#b}", i),
+            ("b", Union::Int(i)) => format!("{:b}", i),
+            ("o", Union::Int(i)) if spec.alternate => format!("{:#o}", i),
+            ("o", Union::Int(i)) => format!("{:o}", i),
+            ("e", Union::Int(i)) => format!("{:e}", i),
+            ("e", Union::Float(f)) => match spec.precision {
+                Some(precision) => format!("{:.*e}", precision, f),
+                None => format!("{:e}", f),
+            },
+            ("", Union::Float(f)) => match spec.precision {
+                Some(precision) => format!("{:.*}", precision, f),
+                None => f.to_string(),
+            },
+            ("", Union::String(s)) => match spec.precision {
+                Some(precision) => s.chars().take(precision).collect(),
+                None => s.to_string(),
+            },
+            ("", _) => self.display(&value, scope)?,
+            (ty, _) => {
+                return Err(format_error(format!(
+                    "{{:{}}} can't format {}",
+                    ty,
+                    value.ty()
+                )))
+            }
+        };
+
+        let formatted = match &value {
+            Union::Int(i) if spec.plus && *i >= 0 => format!("+{}", formatted),
+            Union::Float(f) if spec.plus && f.is_sign_positive() => format!("+{}", formatted),
+            _ => formatted,
+        };
+
+        self.limits
+            .check_string_len(spec.padded_len(&formatted, numeric))?;
+
+        Ok(spec.pad(formatted, numeric))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn format() {
+        let mut engine = Engine::<()>::new();
+        engine.register_display(|s: &std::ops::Range<i32>| format!("{} to {}", s.start, s.end));
+        engine.register_fn("host_range", |start: i32, end: i32| start..end);
+
+        let eval = |source: &str| eval_with(&engine, source);
+
+        assert_eq!(
+            eval(r#"let name = "orc"; format("{} has {:.2} hp", name, 10.5)"#),
+            "orc has 10.50 hp"
+        );
+        assert_eq!(
+            eval(r#"let hp = 7; format("{hp:>4}|{0:<3}|{0:^5}|{1:+05}", "a", 42)"#),
+            "   7|a  |  a  |+0042"
+        );
+        assert_eq!(eval(r#"format("{{{:#x}}}", 255)"#), "{0xff}");
+        assert_eq!(
+            eval(r#"let m = map(); m.insert("a", [1, 2.0, "x"]); format("{:?}", m)"#),
+            r#"{"a": [1, 2.0, "x"]}"#
+        );
+        assert_eq!(
+            eval(r#"format("{:#?}", [[1], []])"#),
+            "[\n    [\n        1,\n    ],\n    [],\n]"
+        );
+        assert_eq!(
+            eval(r#"format("{} {:?}", host_range(1, 3), [host_range(0, 1)])"#),
+            "1 to 3 [0 to 1]"
+        );
+        assert_eq!(eval("[1, 2].to_string()"), "[1, 2]");
+        assert_eq!(
+            eval(r#"let a = [1]; a.push(&a); format("{:?} {}", a, a)"#),
+            "[1, [1, [...]]] [1, [1, [...]]]"
+        );
+
+        assert!(engine.eval(&mut (), r#"format("{} {}", 1)"#).is_err());
+        assert!(engine.eval(&mut (), r#"format("{:y}", 1)"#).is_err());
+    }
+}
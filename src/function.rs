@@ -2,11 +2,15 @@ use crate::ast::*;
 use crate::control_flow::*;
 use crate::embedded_ctx_fn::*;
 use crate::embedded_fn::*;
+use crate::embedded_runtime_fn::*;
 use crate::error::*;
+use crate::limits::*;
 use crate::runtime::*;
 use crate::scope::*;
 use crate::span::*;
+use crate::symbol::*;
 use crate::variant::*;
+use crate::vm::*;
 use std::any::TypeId;
 use std::sync::Arc;
 
@@ -24,15 +28,24 @@ pub(crate) fn by_value<T: Variant + Clone>(data: Union) -> T {
     }
 }
 
+/// Error returned by embedded functions when their input doesn't match the signature
+/// they were dispatched on, the span is filled in by [`FnType::run`].
+pub(crate) fn unreachable() -> Error {
+    Error::from_raw(ErrorKind::Unreachable, "")
+}
+
 #[derive(Debug)]
 pub enum FnType<T> {
     Native {
         block: Arc<Spanned<Block>>,
-        parameter_idents: Arc<Vec<String>>,
+        parameter_idents: Arc<Vec<Symbol>>,
         return_type: UnionType,
+        /// The body compiled for the VM, [`None`] when defined by the tree walker.
+        chunk: Option<Arc<Chunk>>,
     },
     EmbeddedFn(EmbeddedFn),
     EmbeddedCtxFn(EmbeddedCtxFn<T>),
+    EmbeddedRuntimeFn(EmbeddedRuntimeFn<T>),
 }
 
 impl<T> Clone for FnType<T> {
@@ -42,13 +55,18 @@ impl<T> Clone for FnType<T> {
                 block,
                 parameter_idents,
                 return_type,
+                chunk,
             } => Self::Native {
                 block: block.clone(),
                 parameter_idents: parameter_idents.clone(),
                 return_type: return_type.clone(),
+                chunk: chunk.clone(),
             },
             Self::EmbeddedFn(embedded_fn) => Self::EmbeddedFn(embedded_fn.clone()),
             Self::EmbeddedCtxFn(embedded_ctx_fn) => Self::EmbeddedCtxFn(embedded_ctx_fn.clone()),
+            Self::EmbeddedRuntimeFn(embedded_runtime_fn) => {
+                Self::EmbeddedRuntimeFn(embedded_runtime_fn.clone())
+            }
         }
     }
 }
@@ -67,29 +85,65 @@ impl<T> FnType<T> {
         runtime: &mut Runtime<T>,
         scope: &mut Scope<T>,
         input: Vec<Variable>,
-    ) -> Result<Variable, Error> {
+    ) -> Result<Variable, ControlFlow> {
+        if !runtime.limits.limits_sizes() {
+            return self.run_unchecked(span, runtime, scope, input);
+        }
+
+        // functions like `push` grow what their reference parameters point to
+        let references = input
+            .iter()
+            .filter(|variable| matches!(variable.ty(), UnionType::Reference(_)))
+            .map(Variable::clone_shared)
+            .collect::<Vec<_>>();
+
+        let returned = self.run_unchecked(span, runtime, scope, input)?;
+
+        runtime.check_size(&returned)?;
+
+        for reference in &references {
+            runtime.check_size(reference)?;
+        }
+
+        Ok(returned)
+    }
+
+    fn run_unchecked(
+        &self,
+        span: &Span,
+        runtime: &mut Runtime<T>,
+        scope: &mut Scope<T>,
+        input: Vec<Variable>,
+    ) -> Result<Variable, ControlFlow> {
         match self {
             Self::Native {
                 block,
                 parameter_idents,
                 return_type,
+                chunk,
             } => {
+                runtime.enter_call()?;
                 scope.sub(true);
 
-                for (ident, union_cell) in parameter_idents.iter().zip(input.into_iter()) {
-                    scope.push(ident, union_cell);
+                for (ident, variable) in parameter_idents.iter().zip(input) {
+                    scope.push(*ident, variable);
                 }
 
-                let returned = match runtime.eval_block(block, scope) {
+                let returned = runtime.check_variables(scope).and_then(|_| {
+                    grow_stack(|| runtime.eval_fn_body(block, chunk.as_deref(), scope))
+                });
+
+                scope.rev_sub();
+                runtime.exit_call();
+
+                let returned = match returned {
                     Ok(v) => Ok(v),
                     Err(err) => match err {
                         ControlFlow::Return(v) => Ok(v),
-                        ControlFlow::Error(err) => Err(err),
+                        err => Err(err),
                     },
                 }?;
 
-                scope.rev_sub();
-
                 if let UnionType::Any = return_type {
                     Ok(returned)
                 } else {
@@ -102,16 +156,67 @@ impl<T> FnType<T> {
             }
             Self::EmbeddedFn(embedded_fn) => match embedded_fn.run(input) {
                 Ok(union) => Ok(Variable::specified(union)),
-                Err(_) => Err(Error::new(ErrorKind::Unreachable, &runtime.source, *span).into()),
+                Err(ControlFlow::Error(Error {
+                    kind: ErrorKind::Unreachable,
+                    ..
+                })) => Err(Error::new(ErrorKind::Unreachable, &runtime.source, *span).into()),
+                Err(err) => Err(err),
             },
             Self::EmbeddedCtxFn(embedded_ctx_fn) => match embedded_ctx_fn.run(runtime.ctx, input) {
                 Ok(union) => Ok(Variable::specified(union)),
-                Err(_) => Err(Error::new(ErrorKind::Unreachable, &runtime.source, *span).into()),
+                Err(ControlFlow::Error(Error {
+                    kind: ErrorKind::Unreachable,
+                    ..
+                })) => Err(Error::new(ErrorKind::Unreachable, &runtime.source, *span).into()),
+                Err(err) => Err(err),
             },
+            Self::EmbeddedRuntimeFn(embedded_runtime_fn) => {
+                match embedded_runtime_fn.run(runtime, scope, input) {
+                    Ok(union) => Ok(Variable::specified(union)),
+                    Err(ControlFlow::Error(Error {
+                        kind: ErrorKind::Unreachable,
+                        ..
+                    })) => Err(Error::new(ErrorKind::Unreachable, &runtime.source, *span).into()),
+                    Err(err) => Err(err),
+                }
+            }
+        }
+    }
+}
+
+/// Converts the return value of an embedded function into the result of a call.
+///
+/// Any [`Variant`] is returned as is, while a `Result` allows embedded functions to
+/// fail with a script error or to alter the control flow, eg. by exiting.
+/// `M` only serves to keep the implementations apart.
+pub trait IntoFnResult<M> {
+    fn into_fn_result(self) -> Result<UnionCell, ControlFlow>;
+}
+
+impl<T: Variant> IntoFnResult<Union> for T {
+    #[inline(always)]
+    fn into_fn_result(self) -> Result<UnionCell, ControlFlow> {
+        Ok(UnionCell::new(self))
+    }
+}
+
+impl<T: Variant> IntoFnResult<Error> for Result<T, Error> {
+    #[inline(always)]
+    fn into_fn_result(self) -> Result<UnionCell, ControlFlow> {
+        match self {
+            Ok(variant) => Ok(UnionCell::new(variant)),
+            Err(err) => Err(ControlFlow::Error(err)),
         }
     }
 }
 
+impl<T: Variant> IntoFnResult<ControlFlow> for Result<T, ControlFlow> {
+    #[inline(always)]
+    fn into_fn_result(self) -> Result<UnionCell, ControlFlow> {
+        self.map(UnionCell::new)
+    }
+}
+
 pub trait IntoEmbeddedFn<T, P, R, U> {
     fn into_embedded_fn(self) -> FnType<T>;
 }
@@ -4,30 +4,78 @@ macro_rules! op {
     ($ident:ident, $fn:ident, $lhs:expr, $rhs:expr, $op:expr) => {
         {
             match $rhs.$fn() {
-                Some(rhs) => match $op.as_str() {
-                    "+" => Some(Union::$ident($lhs + rhs)),
-                    "-" => Some(Union::$ident($lhs - rhs)),
-                    "*" => Some(Union::$ident($lhs * rhs)),
-                    "/" => Some(Union::$ident($lhs / rhs)),
-                    "%" => Some(Union::$ident($lhs % rhs)),
-                    ">" => Some(Union::Bool($lhs > rhs)),
-                    "<" => Some(Union::Bool($lhs < rhs)),
-                    ">=" => Some(Union::Bool($lhs >= rhs)),
-                    "<=" => Some(Union::Bool($lhs <= rhs)),
-                    "==" => Some(Union::Bool($lhs == rhs)),
-                    _ => None,
-                },
+                Some(rhs) => Some(match $op {
+                    BuiltinOp::Add => Union::$ident($lhs + rhs),
+                    BuiltinOp::Sub => Union::$ident($lhs - rhs),
+                    BuiltinOp::Mul => Union::$ident($lhs * rhs),
+                    BuiltinOp::Div => Union::$ident($lhs / rhs),
+                    BuiltinOp::Rem => Union::$ident($lhs % rhs),
+                    BuiltinOp::Gt => Union::Bool($lhs > rhs),
+                    BuiltinOp::Lt => Union::Bool($lhs < rhs),
+                    BuiltinOp::Ge => Union::Bool($lhs >= rhs),
+                    BuiltinOp::Le => Union::Bool($lhs <= rhs),
+                    BuiltinOp::Eq => Union::Bool($lhs == rhs),
+                    BuiltinOp::Ne => Union::Bool($lhs != rhs),
+                }),
                 None => None,
             }
         }
     };
 }
 
-#[inline(always)]
-pub fn internal_binop(lhs: Union, rhs: Union, op: &String) -> Option<Union> {
-    match lhs {
-        Union::Int(lhs) => op!(Int, as_int, lhs, rhs, op),
-        Union::Float(lhs) => op!(Float, as_float, lhs, rhs, op),
-        _ => None,
+/// An operator with a built in meaning for two ints or two floats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuiltinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl BuiltinOp {
+    pub const ALL: [BuiltinOp; 11] = [
+        Self::Add, Self::Sub, Self::Mul, Self::Div, Self::Rem,
+        Self::Gt, Self::Lt, Self::Ge, Self::Le, Self::Eq, Self::Ne,
+    ];
+
+    pub fn from_ident(op: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|builtin| builtin.ident() == op)
+    }
+
+    pub fn ident(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Rem => "%",
+            Self::Gt => ">",
+            Self::Lt => "<",
+            Self::Ge => ">=",
+            Self::Le => "<=",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+        }
     }
-}
\ No newline at end of file
+
+    #[inline(always)]
+    pub fn apply(self, lhs: &Union, rhs: &Union) -> Option<Union> {
+        match lhs {
+            Union::Int(lhs) => op!(Int, as_int, *lhs, rhs, self),
+            Union::Float(lhs) => op!(Float, as_float, *lhs, rhs, self),
+            _ => None,
+        }
+    }
+}
+
+#[inline(always)]
+pub fn internal_binop(lhs: Union, rhs: Union, op: &str) -> Option<Union> {
+    BuiltinOp::from_ident(op)?.apply(&lhs, &rhs)
+}
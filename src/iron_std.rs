@@ -1,14 +1,99 @@
+use crate::closure::*;
+use crate::control_flow::*;
+use crate::error::*;
+use crate::fn_storage::*;
+use crate::range::*;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::span::*;
 use crate::variant::*;
 use crate::*;
+use std::convert::TryFrom;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime};
 
+pub mod blob;
+pub mod format;
+pub mod hash;
+pub mod iter;
+pub mod json;
+pub mod map;
+pub mod math;
+pub mod option;
+pub mod rand;
+pub mod regex;
+pub mod time;
+
+pub use iter::*;
+
+/// Reference point for `std::sys::monotonic`.
+static MONOTONIC_START: OnceLock<Instant> = OnceLock::new();
+
+// Every function in `sys` touches the host, an embedder wanting a locked down
+// profile can remove them individually with `Engine::disable_fn("std::sys::<fn>")`.
 def_module! {
     pub mod iron_std {
         mod fs {
 
         }
 
+        mod blob = blob::blob;
+
+        mod hash = hash::hash;
+
+        mod json = json::json;
+
+        mod math = math::math;
+
+        mod rand = rand::rand;
+
+        mod regex = regex::regex;
+
+        mod time = time::time;
+
         mod sys {
+            fn "env"(key: &str) {
+                std::env::var(key).ok().map(Union::from)
+            }
+
+            fn "args"() {
+                std::env::args()
+                    .map(UnionCell::new)
+                    .collect::<Vec<_>>()
+            }
+
+            fn "now"() {
+                let secs = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|since_epoch| since_epoch.as_secs())
+                    .unwrap_or(0);
 
+                unix_secs(secs)
+            }
+
+            fn "monotonic"() {
+                MONOTONIC_START.get_or_init(Instant::now).elapsed().as_secs_f32()
+            }
+
+            fn "sleep"(millis: i32) {
+                std::thread::sleep(Duration::from_millis(millis.max(0) as u64));
+            }
+
+            fn "exit"(code: i32) {
+                Err::<(), _>(ControlFlow::Exit(code))
+            }
+
+            fn "os"() {
+                String::from(std::env::consts::OS)
+            }
+
+            fn "arch"() {
+                String::from(std::env::consts::ARCH)
+            }
+
+            fn "family"() {
+                String::from(std::env::consts::FAMILY)
+            }
         }
     }
 }
@@ -17,98 +102,374 @@ merge_modules! {
     pub mod global_full {
         string;
         ty;
-        option;
+        option::option;
+        option::result;
         array;
+        map::map;
         range;
+        blob::blob_ops;
+        regex::regex;
+        time::time_ops;
+        format::format;
+        iter::iter;
     }
 }
 
-#[derive(Clone)]
-pub struct Range {
-    pub start: i32,
-    pub end: i32,
-}
-
-#[derive(Clone)]
-pub struct RangeIter {
-    pub start: i32,
-    pub end: i32,
-    pub position: i32,
-}
-
 def_module! {
     pub mod range {
         fn "range"(start: i32, end: i32) {
-            Range {
-                start,
-                end,
-            }
+            Range::new(start, end)
         }
 
         fn "into_iter"(range: Range) {
-            RangeIter {
-                start: range.start,
-                end: range.end,
-                position: range.start,
-            }
+            range.iter()
         }
 
         fn "iter_next"(range_iter: &mut RangeIter) {
-            range_iter.position += 1;
+            range_iter.next().map(Union::from)
+        }
+
+        fn "rev"(range: Range) {
+            range.rev()
+        }
 
-            if range_iter.position <= range_iter.end {
-                Some(Union::from(range_iter.position - 1))
-            } else {
-                None
+        fn "step_by"(range: Range, step: i32) {
+            if step <= 0 {
+                return Err(Error::from_raw(
+                    ErrorKind::InvalidArgument,
+                    format!("step must be positive, got {}", step),
+                ));
             }
+
+            let mut iter = range.iter()?;
+            iter.step = step as i64;
+
+            Ok(iter)
+        }
+
+        fn "contains"(range: Range, value: i32) {
+            range.contains(value)
+        }
+
+        fn "len"(range: Range) {
+            match range.len() {
+                Some(len) => Ok(len as i32),
+                None => Err(Error::from_raw(
+                    ErrorKind::InvalidArgument,
+                    format!("{} is unbounded", range),
+                )),
+            }
+        }
+
+        fn "is_empty"(range: Range) {
+            range.is_empty()
         }
     }
 }
 
+/// Seconds since the Unix epoch as an int, which runs out in 2038.
+fn unix_secs(secs: u64) -> Result<i32, Error> {
+    i32::try_from(secs).map_err(|_| {
+        Error::from_raw(
+            ErrorKind::InvalidArgument,
+            format!("{} seconds since the epoch don't fit an int", secs),
+        )
+    })
+}
+
+/// Converts a script index into an index of an array with `len` elements.
+fn array_index(index: i32, len: usize) -> Result<usize, Error> {
+    if index >= 0 && (index as usize) < len {
+        Ok(index as usize)
+    } else {
+        Err(Error::from_raw(
+            ErrorKind::IndexOutOfBounds,
+            format!("index {} is out of bounds for length {}", index, len),
+        ))
+    }
+}
+
+/// Stable merge sort that, unlike `slice::sort_by`, allows the comparison to fail.
+fn merge_sort<F>(mut items: Vec<UnionCell>, less: &mut F) -> Result<Vec<UnionCell>, ControlFlow>
+where
+    F: FnMut(&UnionCell, &UnionCell) -> Result<bool, ControlFlow>,
+{
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+
+    let right = items.split_off(items.len() / 2);
+
+    let mut left = merge_sort(items, less)?.into_iter().peekable();
+    let mut right = merge_sort(right, less)?.into_iter().peekable();
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        if less(r, l)? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+
+    merged.extend(left);
+    merged.extend(right);
+
+    Ok(merged)
+}
+
+/// Sorts the array in place, `less` is called without holding the lock on the array,
+/// so it's free to call back into the script.
+fn sort_array<F>(arr: &mut Mut<Vec<UnionCell>>, mut less: F) -> Result<(), ControlFlow>
+where
+    F: FnMut(&UnionCell, &UnionCell) -> Result<bool, ControlFlow>,
+{
+    let items = arr.map(|a| a.clone());
+    let mut sorted = Some(merge_sort(items, &mut less)?);
+
+    arr.map_mut(|a| *a = sorted.take().unwrap());
+
+    Ok(())
+}
+
+fn array_find<T>(
+    runtime: &mut Runtime<T>,
+    scope: &mut Scope<T>,
+    arr: &Mut<Vec<UnionCell>>,
+    item: &Union,
+) -> Result<Option<usize>, ControlFlow> {
+    let items = arr.map(|a| a.clone());
+
+    for (i, element) in items.into_iter().enumerate() {
+        if values_equal(runtime, scope, &element.into_inner(), item)? {
+            return Ok(Some(i));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Compares bools, arrays and maps structurally and everything else with `==`, values
+/// without an `==` between them, eg. of different types, aren't equal.
+fn values_equal<T>(
+    runtime: &mut Runtime<T>,
+    scope: &mut Scope<T>,
+    a: &Union,
+    b: &Union,
+) -> Result<bool, ControlFlow> {
+    if let (Union::Bool(a), Union::Bool(b)) = (a, b) {
+        return Ok(a == b);
+    }
+
+    if let (Some(a), Some(b)) = (
+        a.downcast_ref::<Vec<UnionCell>>(),
+        b.downcast_ref::<Vec<UnionCell>>(),
+    ) {
+        return cells_equal(runtime, scope, a.iter().zip(b), a.len() == b.len());
+    }
+
+    if let (Some(a), Some(b)) = (a.downcast_ref::<Map>(), b.downcast_ref::<Map>()) {
+        let same_keys = a.len() == b.len() && a.keys().eq(b.keys());
+
+        return cells_equal(runtime, scope, a.values().zip(b.values()), same_keys);
+    }
+
+    let fn_signature = FnSignature {
+        ident: "==".into(),
+        params: vec![a.ty(), b.ty()],
+    };
+
+    let equal = match scope.get_fn(&fn_signature) {
+        Ok(_) => runtime
+            .eval_binop(
+                "==",
+                Span::new(0, 0),
+                Variable::specified(a.clone()),
+                Variable::specified(b.clone()),
+                scope,
+            )?
+            .into_inner(),
+        Err(_) => match crate::internal_binop::internal_binop(a.clone(), b.clone(), "==") {
+            Some(equal) => equal,
+            None => return Ok(false),
+        },
+    };
+
+    Ok(equal.as_bool() == Some(true))
+}
+
+fn cells_equal<'c, T>(
+    runtime: &mut Runtime<T>,
+    scope: &mut Scope<T>,
+    pairs: impl Iterator<Item = (&'c UnionCell, &'c UnionCell)>,
+    same_len: bool,
+) -> Result<bool, ControlFlow> {
+    if !same_len {
+        return Ok(false);
+    }
+
+    for (a, b) in pairs {
+        if !values_equal(runtime, scope, &a.cloned(), &b.cloned())? {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Non destructive iterator over a copy of an array.
+#[derive(Clone)]
+pub struct ArrayIter {
+    pub items: Vec<UnionCell>,
+    pub position: usize,
+}
+
 def_module! {
     pub mod array {
         fn "arr"() {
-            vec![UnionCell::new(4), UnionCell::new(5)]
+            Vec::<UnionCell>::new()
         }
 
         fn "push"(arr: &mut Vec<UnionCell>, item: Union) {
             arr.push(UnionCell::from(item));
         }
 
-        fn "[]"(arr: Mut<Vec<UnionCell>>, index: i32) {
-            arr.map_mut(|u| u[index as usize].get_shared())
+        fn "pop"(arr: &mut Vec<UnionCell>) {
+            arr.pop().map(UnionCell::into_inner)
+        }
+
+        fn "insert"(arr: &mut Vec<UnionCell>, index: i32, item: Union) {
+            // inserting at the end is allowed
+            let index = array_index(index, arr.len() + 1)?;
+            arr.insert(index, UnionCell::from(item));
+
+            Ok::<_, Error>(())
+        }
+
+        fn "remove"(arr: &mut Vec<UnionCell>, index: i32) {
+            let index = array_index(index, arr.len())?;
+
+            Ok::<_, Error>(arr.remove(index).into_inner())
+        }
+
+        fn "len"(arr: &mut Vec<UnionCell>) {
+            arr.len() as i32
+        }
+
+        fn "is_empty"(arr: &mut Vec<UnionCell>) {
+            arr.is_empty()
+        }
+
+        fn "contains"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, arr: Mut<Vec<UnionCell>>, item: Union) {
+            array_find(runtime, scope, &arr, &item).map(|i| i.is_some())
+        }
+
+        fn "index_of"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, arr: Mut<Vec<UnionCell>>, item: Union) {
+            array_find(runtime, scope, &arr, &item).map(|i| i.map(|i| Union::from(i as i32)))
         }
 
-        fn "iter_next"(arr: &mut Vec<UnionCell>) {
-            if arr.len() > 0 {
-                Some(arr.remove(0))
-            } else {
-                None
+        fn "slice"(arr: &mut Vec<UnionCell>, start: i32, end: i32) {
+            if start < 0 || end < start || end as usize > arr.len() {
+                return Err(Error::from_raw(
+                    ErrorKind::IndexOutOfBounds,
+                    format!("{}..{} is out of bounds for length {}", start, end, arr.len()),
+                ));
             }
+
+            Ok(arr[start as usize..end as usize].to_vec())
         }
-    }
-}
 
-def_module! {
-    pub mod option {
-        fn "some"(s: Union) {
-            Some(s)
+        fn "reverse"(arr: &mut Vec<UnionCell>) {
+            arr.reverse();
+        }
+
+        fn "sort"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, arr: Mut<Vec<UnionCell>>) {
+            sort_array(&mut arr, |a, b| {
+                let less = runtime.eval_binop(
+                    "<",
+                    Span::new(0, 0),
+                    Variable::specified(a.clone()),
+                    Variable::specified(b.clone()),
+                    scope,
+                )?;
+
+                Ok(less.map(|u| u.as_bool()) == Some(true))
+            })
         }
 
-        fn "none"() {
-            None::<Union>
+        // the closure either returns whether `a < b` or an int ordering them like `a - b`
+        fn "sort_by"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, arr: Mut<Vec<UnionCell>>, f: Closure) {
+            sort_array(&mut arr, |a, b| {
+                let input = vec![Variable::specified(a.clone()), Variable::specified(b.clone())];
+                let ordering = runtime.call_closure(&f, input, scope)?.into_inner();
+
+                match ordering {
+                    Union::Bool(less) => Ok(less),
+                    Union::Int(ordering) => Ok(ordering < 0),
+                    _ => Err(Error::from_raw(
+                        ErrorKind::TypeMismatch,
+                        "sort_by closure must return a bool or an i32",
+                    )
+                    .into()),
+                }
+            })
         }
 
-        fn "is_some"(o: Option<Union>) {
-            o.is_some()
+        fn "join"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, arr: Mut<Vec<UnionCell>>, separator: SharedString) {
+            let items = arr.map(|arr| arr.iter().map(|item| item.cloned().to_string()).collect::<Vec<_>>());
+            let len = items.iter().map(String::len).sum::<usize>()
+                + separator.len() * items.len().saturating_sub(1);
+
+            runtime.limits.check_string_len(len)?;
+
+            Ok::<_, Error>(items.join(&separator))
         }
 
-        fn "is_none"(o: Option<Union>) {
-            o.is_none()
+        fn "concat"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, arr: Mut<Vec<UnionCell>>, other: Vec<UnionCell>) {
+            arr.map(|arr| {
+                runtime.limits.check_array_len(arr.len() + other.len())?;
+
+                let mut concatenated = arr.clone();
+                concatenated.append(&mut other);
+
+                Ok::<_, Error>(concatenated)
+            })
         }
 
-        fn "unwrap"(o: Option<Union>) {
-            o.unwrap()
+        fn "extend"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, arr: Mut<Vec<UnionCell>>, other: Vec<UnionCell>) {
+            arr.map_mut(|arr| {
+                runtime.limits.check_array_len(arr.len() + other.len())?;
+                arr.append(&mut other);
+
+                Ok::<_, Error>(())
+            })
+        }
+
+        fn "[]"(arr: Mut<Vec<UnionCell>>, index: i32) {
+            arr.map_mut(|u| {
+                let index = array_index(index, u.len())?;
+
+                Ok::<_, Error>(u[index].get_shared())
+            })
+        }
+
+        fn "[]"(arr: Mut<Vec<UnionCell>>, range: Range) {
+            arr.map(|u| Ok::<_, Error>(u[range.indices(u.len())?].to_vec()))
+        }
+
+        fn "into_iter"(arr: Vec<UnionCell>) {
+            ArrayIter {
+                items: arr,
+                position: 0,
+            }
+        }
+
+        fn "iter_next"(iter: &mut ArrayIter) {
+            let item = iter.items.get(iter.position).map(UnionCell::cloned);
+            iter.position += 1;
+
+            item
         }
     }
 }
@@ -121,14 +482,281 @@ def_module! {
     }
 }
 
+/// Gets the byte range `start..end` of `s`, failing if it's out of bounds or
+/// doesn't lie on char boundaries.
+fn str_range(s: &str, start: i32, end: i32) -> Result<&str, Error> {
+    if start < 0 || end < start {
+        return Err(Error::from_raw(
+            ErrorKind::IndexOutOfBounds,
+            format!("invalid range {}..{}", start, end),
+        ));
+    }
+
+    s.get(start as usize..end as usize).ok_or_else(|| {
+        Error::from_raw(
+            ErrorKind::IndexOutOfBounds,
+            format!("{}..{} is out of bounds or not on a char boundary", start, end),
+        )
+    })
+}
+
+fn str_array<'a>(iter: impl Iterator<Item = &'a str>) -> Vec<UnionCell> {
+    iter.map(|s| UnionCell::new(s.to_string())).collect()
+}
+
+// strings are taken as `SharedString` rather than `&str`, since a `&str` doesn't
+// outlive the argument it was borrowed from
 def_module! {
     pub mod string {
-        fn "+"(a: &str, b: Union) {
+        fn "+"(a: SharedString, b: Union) {
             format!("{}{}", a, b)
         }
 
-        fn "=="(lhs: &str, rhs: &str) {
+        fn "=="(lhs: SharedString, rhs: SharedString) {
             lhs == rhs
         }
+
+        fn "!="(lhs: SharedString, rhs: SharedString) {
+            lhs != rhs
+        }
+
+        fn "<"(lhs: SharedString, rhs: SharedString) {
+            lhs < rhs
+        }
+
+        fn ">"(lhs: SharedString, rhs: SharedString) {
+            lhs > rhs
+        }
+
+        fn "<="(lhs: SharedString, rhs: SharedString) {
+            lhs <= rhs
+        }
+
+        fn ">="(lhs: SharedString, rhs: SharedString) {
+            lhs >= rhs
+        }
+
+        fn "[]"(s: SharedString, index: i32) {
+            let rest = str_range(&s, index, s.len() as i32)?;
+
+            match rest.chars().next() {
+                Some(c) => Ok(c.to_string()),
+                None => Err(Error::from_raw(
+                    ErrorKind::IndexOutOfBounds,
+                    format!("index {} is out of bounds", index),
+                )),
+            }
+        }
+
+        fn "[]"(s: SharedString, range: Range) {
+            let indices = range.indices(s.len())?;
+
+            str_range(&s, indices.start as i32, indices.end as i32).map(|s| s.to_string())
+        }
+
+        fn "len"(s: SharedString) {
+            s.len() as i32
+        }
+
+        fn "is_empty"(s: SharedString) {
+            s.is_empty()
+        }
+
+        fn "chars"(s: SharedString) {
+            s.chars()
+                .map(|c| UnionCell::new(c.to_string()))
+                .collect::<Vec<_>>()
+        }
+
+        fn "split"(s: SharedString, pattern: SharedString) {
+            str_array(s.split(pattern.as_str()))
+        }
+
+        fn "lines"(s: SharedString) {
+            str_array(s.lines())
+        }
+
+        fn "trim"(s: SharedString) {
+            s.trim().to_string()
+        }
+
+        fn "trim_start"(s: SharedString) {
+            s.trim_start().to_string()
+        }
+
+        fn "trim_end"(s: SharedString) {
+            s.trim_end().to_string()
+        }
+
+        fn "to_upper"(s: SharedString) {
+            s.to_uppercase()
+        }
+
+        fn "to_lower"(s: SharedString) {
+            s.to_lowercase()
+        }
+
+        fn "contains"(s: SharedString, pattern: SharedString) {
+            s.contains(pattern.as_str())
+        }
+
+        fn "starts_with"(s: SharedString, pattern: SharedString) {
+            s.starts_with(pattern.as_str())
+        }
+
+        fn "ends_with"(s: SharedString, pattern: SharedString) {
+            s.ends_with(pattern.as_str())
+        }
+
+        fn "replace"(s: SharedString, from: SharedString, to: SharedString) {
+            s.replace(from.as_str(), &to)
+        }
+
+        fn "find"(s: SharedString, pattern: SharedString) {
+            s.find(pattern.as_str()).map(|i| Union::from(i as i32))
+        }
+
+        fn "substring"(s: SharedString, start: i32, end: i32) {
+            str_range(&s, start, end).map(|s| s.to_string())
+        }
+
+        fn "repeat"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, s: SharedString, n: i32) {
+            if n < 0 {
+                return Err(Error::from_raw(
+                    ErrorKind::InvalidArgument,
+                    format!("can't repeat a string {} times", n),
+                ));
+            }
+
+            runtime.limits.check_string_len(s.len().saturating_mul(n as usize))?;
+
+            Ok(s.repeat(n as usize))
+        }
+
+        fn "parse_int"(s: SharedString) {
+            s.trim().parse::<i32>().map_err(|err| {
+                Error::from_raw(ErrorKind::InvalidArgument, format!("{:?}: {}", s, err))
+            })
+        }
+
+        fn "parse_float"(s: SharedString) {
+            s.trim().parse::<f32>().map_err(|err| {
+                Error::from_raw(ErrorKind::InvalidArgument, format!("{:?}: {}", s, err))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::prelude::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn sys_exit() {
+        let engine = Engine::<()>::new();
+
+        let result = engine.eval(&mut (), "try { std::sys::exit(3); } catch {}");
+
+        assert!(matches!(
+            result,
+            Err(Error {
+                kind: ErrorKind::Exit(3),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn sys_now() {
+        assert!(super::unix_secs(i32::MAX as u64).is_ok());
+        assert!(super::unix_secs(i32::MAX as u64 + 1).is_err());
+    }
+
+    #[test]
+    fn sys_disable_fn() {
+        let mut engine = Engine::<()>::new();
+        engine.disable_fn("std::sys::env");
+
+        assert!(engine.eval(&mut (), "std::sys::os()").is_ok());
+        assert!(matches!(
+            engine.eval(&mut (), "std::sys::env(\"HOME\")"),
+            Err(Error {
+                kind: ErrorKind::UndefinedFunction,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn string_methods() {
+        assert_eq!(eval("\"  Iron \".trim().to_upper()"), "IRON");
+        assert_eq!(eval("\"a,b,c\".split(\",\")[1]"), "b");
+        assert_eq!(eval("\"héllo\".substring(0, 3)"), "hé");
+        assert_eq!(eval("\"42\".parse_int() + 1"), "43");
+        assert_eq!(eval("\"abc\"[1]"), "b");
+        assert_eq!(eval("\"abc\" < \"abd\""), "true");
+        assert_eq!(eval("2.5.to_string() + \"!\""), "2.5!");
+
+        assert!(matches!(
+            try_eval("\"héllo\".substring(0, 2)"),
+            Err(Error {
+                kind: ErrorKind::IndexOutOfBounds,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn array_methods() {
+        assert_eq!(
+            eval("let a = [3, 1, 2]; let s = 0; for x in a { s += x; } s + a.len()"),
+            "9"
+        );
+        assert_eq!(eval("let a = [3, 1, 2]; a.sort(); a.join(\",\")"), "1,2,3");
+        assert_eq!(
+            eval("let a = [\"bb\", \"a\", \"ccc\"]; a.sort_by(|x, y| x.len() - y.len()); a[2]"),
+            "ccc"
+        );
+        assert_eq!(eval("let a = [1, 2]; a.extend([3]); a.insert(0, 0); a.pop(); a.len()"), "3");
+        assert_eq!(eval("[1, 2, 3].contains(2)"), "true");
+        assert_eq!(eval("[1, \"a\"].contains(\"a\")"), "true");
+        assert_eq!(eval("[1, \"a\"].contains(2.0)"), "false");
+        assert_eq!(eval("[true, false].contains(false)"), "true");
+        assert_eq!(eval("[[1], [2]].contains([2])"), "true");
+        assert_eq!(eval("[[1], [2, 3]].contains([2])"), "false");
+        assert_eq!(
+            eval("[[1], [\"b\", [true]]].index_of([\"b\", [true]]).unwrap()"),
+            "1"
+        );
+        assert_eq!(
+            eval("let m = map(); m.insert(\"k\", [1]); [1, m].contains(m)"),
+            "true"
+        );
+
+        assert!(matches!(
+            try_eval("let a = [1]; a[1]"),
+            Err(Error {
+                kind: ErrorKind::IndexOutOfBounds,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(eval("let s = 0; for i in 0..4 { s += i; } s"), "6");
+        assert_eq!(eval("let s = 0; for i in 1..=4 { s += i; } s"), "10");
+        assert_eq!(eval("(0..3).rev().collect().join(\",\")"), "2,1,0");
+        assert_eq!(eval("(0..10).step_by(4).collect().join(\",\")"), "0,4,8");
+        assert_eq!(eval("(5..).take(2).collect().join(\",\")"), "5,6");
+        assert_eq!(eval("[1, 2, 3, 4][1..3].join(\",\")"), "2,3");
+        assert_eq!(eval("\"hello world\"[..5]"), "hello");
+        assert_eq!(eval("type_of(1..2)"), "Range");
+        assert_eq!(
+            eval("let x = 7; match x { 0 => \"zero\", 1..=9 => \"digit\", _ => \"big\" }"),
+            "digit"
+        );
+        assert_eq!(eval("match 12 { ..10 => 0, n => n * 2 }"), "24");
     }
 }
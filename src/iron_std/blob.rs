@@ -0,0 +1,470 @@
+use crate::error::*;
+use crate::range::*;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::variant::*;
+use std::convert::TryFrom;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::from_raw(ErrorKind::InvalidArgument, message)
+}
+
+/// Unescapes the inside of a `b"..."` literal, `\xNN`, `\n`, `\r`, `\t`, `\0`, `\\`
+/// and `\"` are supported.
+pub fn parse_literal(s: &str) -> Result<Blob, &'static str> {
+    let mut bytes = s.bytes();
+    let mut blob = Blob::with_capacity(s.len());
+
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            blob.push(byte);
+            continue;
+        }
+
+        let escaped = match bytes.next() {
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b't') => b'\t',
+            Some(b'0') => b'\0',
+            Some(b'\\') => b'\\',
+            Some(b'"') => b'"',
+            Some(b'x') => {
+                let digits = [bytes.next(), bytes.next()];
+
+                match digits {
+                    [Some(hi), Some(lo)] => match (hex_value(hi), hex_value(lo)) {
+                        (Some(hi), Some(lo)) => hi << 4 | lo,
+                        _ => return Err("Invalid \\x escape in blob literal"),
+                    },
+                    _ => return Err("Invalid \\x escape in blob literal"),
+                }
+            }
+            _ => return Err("Unknown escape in blob literal"),
+        };
+
+        blob.push(escaped);
+    }
+
+    Ok(blob)
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+pub fn to_hex(blob: &[u8]) -> String {
+    blob.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(s: &str) -> Result<Blob, Error> {
+    if !s.len().is_multiple_of(2) {
+        return Err(invalid(format!("hex string of odd length {}", s.len())));
+    }
+
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| match (hex_value(pair[0]), hex_value(pair[1])) {
+            (Some(hi), Some(lo)) => Ok(hi << 4 | lo),
+            _ => Err(invalid(format!(
+                "invalid hex digits {:?}",
+                String::from_utf8_lossy(pair)
+            ))),
+        })
+        .collect()
+}
+
+/// Standard base64 with padding.
+pub fn to_base64(blob: &[u8]) -> String {
+    let mut out = String::with_capacity(blob.len().div_ceil(3) * 4);
+
+    for chunk in blob.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+            bits | (*byte as u32) << (16 - 8 * i)
+        });
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+pub fn from_base64(s: &str) -> Result<Blob, Error> {
+    let s = s.as_bytes();
+
+    if !s.len().is_multiple_of(4) {
+        return Err(invalid(format!("base64 string of length {}", s.len())));
+    }
+
+    let mut blob = Blob::with_capacity(s.len() / 4 * 3);
+
+    for (n, chunk) in s.chunks(4).enumerate() {
+        let last = n == s.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+
+        if padding > 2 || (padding > 0 && !last) {
+            return Err(invalid("misplaced base64 padding"));
+        }
+
+        let mut bits = 0u32;
+
+        for (i, c) in chunk[..4 - padding].iter().enumerate() {
+            let value = BASE64
+                .iter()
+                .position(|b| b == c)
+                .ok_or_else(|| invalid(format!("invalid base64 character {:?}", *c as char)))?;
+
+            bits |= (value as u32) << (18 - 6 * i);
+        }
+
+        blob.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+
+    Ok(blob)
+}
+
+fn byte(value: i32) -> Result<u8, Error> {
+    u8::try_from(value).map_err(|_| invalid(format!("{} is not a byte", value)))
+}
+
+fn narrow<N: TryFrom<i32>>(value: i32, ty: &str) -> Result<N, Error> {
+    N::try_from(value).map_err(|_| invalid(format!("{} doesn't fit in {}", value, ty)))
+}
+
+fn index(blob: &[u8], index: i32) -> Result<usize, Error> {
+    if index >= 0 && (index as usize) < blob.len() {
+        Ok(index as usize)
+    } else {
+        Err(Error::from_raw(
+            ErrorKind::IndexOutOfBounds,
+            format!("index {} is out of bounds for length {}", index, blob.len()),
+        ))
+    }
+}
+
+/// The `N` bytes at `offset`.
+fn read<const N: usize>(blob: &[u8], offset: i32) -> Result<[u8; N], Error> {
+    usize::try_from(offset)
+        .ok()
+        .and_then(|start| blob.get(start..start.checked_add(N)?))
+        .map(|bytes| <[u8; N]>::try_from(bytes).unwrap())
+        .ok_or_else(|| {
+            Error::from_raw(
+                ErrorKind::IndexOutOfBounds,
+                format!(
+                    "reading {} bytes at {} is out of bounds for length {}",
+                    N,
+                    offset,
+                    blob.len()
+                ),
+            )
+        })
+}
+
+/// Overwrites the bytes at `offset`, growing the blob if they run past its end.
+fn write(blob: &mut Blob, offset: i32, bytes: &[u8]) -> Result<(), Error> {
+    let start = match usize::try_from(offset) {
+        Ok(start) if start <= blob.len() => start,
+        _ => {
+            return Err(Error::from_raw(
+                ErrorKind::IndexOutOfBounds,
+                format!(
+                    "writing at {} is out of bounds for length {}",
+                    offset,
+                    blob.len()
+                ),
+            ))
+        }
+    };
+
+    let end = start + bytes.len();
+
+    if end > blob.len() {
+        blob.resize(end, 0);
+    }
+
+    blob[start..end].copy_from_slice(bytes);
+
+    Ok(())
+}
+
+fn u32_to_int(value: u32) -> Result<i32, Error> {
+    i32::try_from(value).map_err(|_| invalid(format!("{} doesn't fit in i32", value)))
+}
+
+def_module! {
+    pub mod blob {
+        fn "from_hex"(s: &str) {
+            from_hex(s)
+        }
+
+        fn "from_base64"(s: &str) {
+            from_base64(s)
+        }
+    }
+}
+
+// Integers are read and written through `i32`, a `u32` that doesn't fit is an error
+// and floats are read and written through `f32`.
+def_module! {
+    pub mod blob_ops {
+        fn "blob"() {
+            Blob::new()
+        }
+
+        fn "blob"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, len: i32) {
+            let len = usize::try_from(len).map_err(|_| invalid(format!("negative blob length {}", len)))?;
+
+            runtime.limits.check_array_len(len)?;
+
+            Ok::<_, Error>(vec![0u8; len])
+        }
+
+        fn "[]"(b: Mut<Blob>, i: i32) {
+            b.map(|b| Ok::<_, Error>(b[index(b, i)?] as i32))
+        }
+
+        fn "[]"(b: Mut<Blob>, range: Range) {
+            b.map(|b| Ok::<_, Error>(b[range.indices(b.len())?].to_vec()))
+        }
+
+        fn "len"(b: &mut Blob) {
+            b.len() as i32
+        }
+
+        fn "is_empty"(b: &mut Blob) {
+            b.is_empty()
+        }
+
+        fn "push"(b: &mut Blob, value: i32) {
+            b.push(byte(value)?);
+
+            Ok::<_, Error>(())
+        }
+
+        fn "pop"(b: &mut Blob) {
+            b.pop().map(|byte| Union::Int(byte as i32))
+        }
+
+        fn "set"(b: &mut Blob, i: i32, value: i32) {
+            let i = index(b, i)?;
+            b[i] = byte(value)?;
+
+            Ok::<_, Error>(())
+        }
+
+        fn "extend"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, b: Mut<Blob>, other: Blob) {
+            b.map_mut(|b| {
+                runtime.limits.check_array_len(b.len() + other.len())?;
+                b.extend_from_slice(&other);
+
+                Ok::<_, Error>(())
+            })
+        }
+
+        fn "+"(a: Blob, b: Blob) {
+            let mut a = a;
+            a.extend_from_slice(&b);
+            a
+        }
+
+        fn "=="(a: Blob, b: Blob) {
+            a == b
+        }
+
+        fn "!="(a: Blob, b: Blob) {
+            a != b
+        }
+
+        fn "to_blob"(s: SharedString) {
+            s.as_bytes().to_vec()
+        }
+
+        fn "decode_utf8"(b: Blob) {
+            String::from_utf8(b).map_err(|err| {
+                invalid(format!("invalid utf-8: {}", err.utf8_error()))
+            })
+        }
+
+        fn "to_hex"(b: Blob) {
+            to_hex(&b)
+        }
+
+        fn "to_base64"(b: Blob) {
+            to_base64(&b)
+        }
+
+        fn "read_u8"(b: &mut Blob, offset: i32) {
+            read::<1>(b, offset).map(|bytes| bytes[0] as i32)
+        }
+
+        fn "read_i8"(b: &mut Blob, offset: i32) {
+            read::<1>(b, offset).map(|bytes| bytes[0] as i8 as i32)
+        }
+
+        fn "read_u16_le"(b: &mut Blob, offset: i32) {
+            read(b, offset).map(|bytes| u16::from_le_bytes(bytes) as i32)
+        }
+
+        fn "read_u16_be"(b: &mut Blob, offset: i32) {
+            read(b, offset).map(|bytes| u16::from_be_bytes(bytes) as i32)
+        }
+
+        fn "read_i16_le"(b: &mut Blob, offset: i32) {
+            read(b, offset).map(|bytes| i16::from_le_bytes(bytes) as i32)
+        }
+
+        fn "read_i16_be"(b: &mut Blob, offset: i32) {
+            read(b, offset).map(|bytes| i16::from_be_bytes(bytes) as i32)
+        }
+
+        fn "read_u32_le"(b: &mut Blob, offset: i32) {
+            read(b, offset).and_then(|bytes| u32_to_int(u32::from_le_bytes(bytes)))
+        }
+
+        fn "read_u32_be"(b: &mut Blob, offset: i32) {
+            read(b, offset).and_then(|bytes| u32_to_int(u32::from_be_bytes(bytes)))
+        }
+
+        fn "read_i32_le"(b: &mut Blob, offset: i32) {
+            read(b, offset).map(i32::from_le_bytes)
+        }
+
+        fn "read_i32_be"(b: &mut Blob, offset: i32) {
+            read(b, offset).map(i32::from_be_bytes)
+        }
+
+        fn "read_f32_le"(b: &mut Blob, offset: i32) {
+            read(b, offset).map(f32::from_le_bytes)
+        }
+
+        fn "read_f32_be"(b: &mut Blob, offset: i32) {
+            read(b, offset).map(f32::from_be_bytes)
+        }
+
+        fn "read_f64_le"(b: &mut Blob, offset: i32) {
+            read(b, offset).map(|bytes| f64::from_le_bytes(bytes) as f32)
+        }
+
+        fn "read_f64_be"(b: &mut Blob, offset: i32) {
+            read(b, offset).map(|bytes| f64::from_be_bytes(bytes) as f32)
+        }
+
+        fn "write_u8"(b: &mut Blob, offset: i32, value: i32) {
+            write(b, offset, &[narrow::<u8>(value, "u8")?])
+        }
+
+        fn "write_i8"(b: &mut Blob, offset: i32, value: i32) {
+            write(b, offset, &narrow::<i8>(value, "i8")?.to_le_bytes())
+        }
+
+        fn "write_u16_le"(b: &mut Blob, offset: i32, value: i32) {
+            write(b, offset, &narrow::<u16>(value, "u16")?.to_le_bytes())
+        }
+
+        fn "write_u16_be"(b: &mut Blob, offset: i32, value: i32) {
+            write(b, offset, &narrow::<u16>(value, "u16")?.to_be_bytes())
+        }
+
+        fn "write_i16_le"(b: &mut Blob, offset: i32, value: i32) {
+            write(b, offset, &narrow::<i16>(value, "i16")?.to_le_bytes())
+        }
+
+        fn "write_i16_be"(b: &mut Blob, offset: i32, value: i32) {
+            write(b, offset, &narrow::<i16>(value, "i16")?.to_be_bytes())
+        }
+
+        fn "write_u32_le"(b: &mut Blob, offset: i32, value: i32) {
+            write(b, offset, &narrow::<u32>(value, "u32")?.to_le_bytes())
+        }
+
+        fn "write_u32_be"(b: &mut Blob, offset: i32, value: i32) {
+            write(b, offset, &narrow::<u32>(value, "u32")?.to_be_bytes())
+        }
+
+        fn "write_i32_le"(b: &mut Blob, offset: i32, value: i32) {
+            write(b, offset, &value.to_le_bytes())
+        }
+
+        fn "write_i32_be"(b: &mut Blob, offset: i32, value: i32) {
+            write(b, offset, &value.to_be_bytes())
+        }
+
+        fn "write_f32_le"(b: &mut Blob, offset: i32, value: f32) {
+            write(b, offset, &value.to_le_bytes())
+        }
+
+        fn "write_f32_be"(b: &mut Blob, offset: i32, value: f32) {
+            write(b, offset, &value.to_be_bytes())
+        }
+
+        fn "write_f64_le"(b: &mut Blob, offset: i32, value: f32) {
+            write(b, offset, &(value as f64).to_le_bytes())
+        }
+
+        fn "write_f64_be"(b: &mut Blob, offset: i32, value: f32) {
+            write(b, offset, &(value as f64).to_be_bytes())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn encodings() {
+        for (bytes, encoded) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+        ] {
+            assert_eq!(to_base64(bytes), encoded);
+            assert_eq!(from_base64(encoded).unwrap(), bytes);
+        }
+
+        assert!(from_base64("Zg=a").is_err());
+        assert_eq!(from_hex("00fFa0").unwrap(), vec![0, 255, 160]);
+        assert_eq!(to_hex(&[0, 255, 160]), "00ffa0");
+        assert!(from_hex("abc").is_err());
+        assert_eq!(parse_literal("a\\x01\\\"").unwrap(), b"a\x01\"");
+    }
+
+    #[test]
+    fn methods() {
+        assert_eq!(eval("b\"ab\\x00\""), "b\"ab\\x00\"");
+        assert_eq!(
+            eval("let b = b\"abc\"; b.push(100); [b[3], b.len()].to_string()"),
+            "[100, 4]"
+        );
+        assert_eq!(eval("let b = b\"abcd\"; b[1..3]"), "b\"bc\"");
+        assert_eq!(eval("(b\"ab\" + b\"c\").decode_utf8()"), "abc");
+        assert_eq!(eval("\"hi\".to_blob().to_hex()"), "6869");
+        assert_eq!(eval("std::blob::from_base64(\"aGk=\") == b\"hi\""), "true");
+
+        let source = "
+            let b = blob();
+            b.write_u16_be(0, 258);
+            b.write_i32_le(2, -2);
+            b.write_f32_le(6, 1.5);
+            [b.to_hex(), b.read_u16_le(0), b.read_i32_le(2), b.read_f32_le(6)].to_string()
+        ";
+
+        assert_eq!(eval(source), "[\"0102feffffff0000c03f\", 513, -2, 1.5]");
+
+        assert!(try_eval("b\"\\xff\".decode_utf8()").is_err());
+        assert!(try_eval("b\"ab\".read_u16_le(1)").is_err());
+        assert!(try_eval("let b = blob(); b.push(256)").is_err());
+        assert!(try_eval("b\"\\xff\\xff\\xff\\xff\".read_u32_le(0)").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use crate::runtime::*;
+use crate::scope::*;
+use crate::variant::*;
+
+// `format` takes up to `MAX_FORMAT_ARGS` arguments, one overload per count.
+def_module! {
+    pub mod format {
+        fn "to_string"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, value: Union) {
+            runtime.display(&value, scope)
+        }
+
+        fn "to_debug"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, value: Union) {
+            runtime.debug(&value, false, scope)
+        }
+
+        fn "format"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, fmt: SharedString) {
+            runtime.format(&fmt, &[], scope)
+        }
+
+        fn "format"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, fmt: SharedString, a: Union) {
+            runtime.format(&fmt, &[a], scope)
+        }
+
+        fn "format"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, fmt: SharedString, a: Union, b: Union) {
+            runtime.format(&fmt, &[a, b], scope)
+        }
+
+        fn "format"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, fmt: SharedString, a: Union, b: Union, c: Union) {
+            runtime.format(&fmt, &[a, b, c], scope)
+        }
+
+        fn "format"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, fmt: SharedString, a: Union, b: Union, c: Union, d: Union) {
+            runtime.format(&fmt, &[a, b, c, d], scope)
+        }
+
+        fn "format"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, fmt: SharedString, a: Union, b: Union, c: Union, d: Union, e: Union) {
+            runtime.format(&fmt, &[a, b, c, d, e], scope)
+        }
+
+        fn "format"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, fmt: SharedString, a: Union, b: Union, c: Union, d: Union, e: Union, f: Union) {
+            runtime.format(&fmt, &[a, b, c, d, e, f], scope)
+        }
+
+        fn "format"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, fmt: SharedString, a: Union, b: Union, c: Union, d: Union, e: Union, f: Union, g: Union) {
+            runtime.format(&fmt, &[a, b, c, d, e, f, g], scope)
+        }
+    }
+}
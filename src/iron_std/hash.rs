@@ -0,0 +1,336 @@
+use crate::error::*;
+use crate::range::*;
+use crate::shared::*;
+use crate::variant::*;
+use fnv::FnvHasher;
+use std::hash::Hasher;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+}
+
+/// CRC-32 as used by zlib and PNG.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// 64 bit FNV-1a.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+pub fn sha256(bytes: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+
+    message.extend_from_slice(&(bytes.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (state, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 32];
+
+    for (bytes, word) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+
+    digest
+}
+
+fn hex(bytes: &[u8]) -> String {
+    crate::iron_std::blob::to_hex(bytes)
+}
+
+/// Feeds `union` into `hasher` with a tag per type, so `1`, `"1"` and `[1]` all differ.
+/// Nothing depends on addresses, `TypeId`s or endianness, which keeps the result stable
+/// across runs and platforms.
+fn hash_union(
+    union: &Union,
+    hasher: &mut FnvHasher,
+    visiting: &mut Vec<*const Lock<Union>>,
+) -> Result<(), Error> {
+    match union {
+        Union::Int(i) => {
+            hasher.write_u8(0);
+            hasher.write(&i.to_le_bytes());
+        }
+        Union::Float(f) => {
+            hasher.write_u8(1);
+
+            // `0.0 == -0.0`, so they have to hash the same
+            let bits = if *f == 0.0 { 0 } else { f.to_bits() };
+
+            hasher.write(&bits.to_le_bytes());
+        }
+        Union::Bool(b) => {
+            hasher.write_u8(2);
+            hasher.write_u8(*b as u8);
+        }
+        Union::String(s) => {
+            hasher.write_u8(3);
+            hasher.write(&(s.len() as u64).to_le_bytes());
+            hasher.write(s.as_bytes());
+        }
+        Union::Unit(()) => hasher.write_u8(4),
+        Union::Type(ty) => {
+            let name = ty.to_string();
+
+            hasher.write_u8(5);
+            hasher.write(&(name.len() as u64).to_le_bytes());
+            hasher.write(name.as_bytes());
+        }
+        Union::Range(Range {
+            start,
+            end,
+            inclusive,
+        }) => {
+            hasher.write_u8(6);
+
+            for bound in [start, end] {
+                match bound {
+                    Some(bound) => {
+                        hasher.write_u8(1);
+                        hasher.write(&bound.to_le_bytes());
+                    }
+                    None => hasher.write_u8(0),
+                }
+            }
+
+            hasher.write_u8(*inclusive as u8);
+        }
+        Union::Blob(b) => {
+            hasher.write_u8(7);
+            hasher.write(&(b.len() as u64).to_le_bytes());
+            hasher.write(b);
+        }
+        Union::Reference(variable) => hash_cell(&variable.union, hasher, visiting)?,
+        Union::Variant(variant) => {
+            let any = Variant::as_any(&**variant);
+
+            if let Some(items) = any.downcast_ref::<Vec<UnionCell>>() {
+                hasher.write_u8(8);
+                hasher.write(&(items.len() as u64).to_le_bytes());
+
+                for item in items {
+                    hash_cell(item, hasher, visiting)?;
+                }
+            } else if let Some(map) = any.downcast_ref::<Map>() {
+                hasher.write_u8(9);
+                hasher.write(&(map.len() as u64).to_le_bytes());
+
+                for (key, value) in map {
+                    hasher.write(&(key.len() as u64).to_le_bytes());
+                    hasher.write(key.as_bytes());
+                    hash_cell(value, hasher, visiting)?;
+                }
+            } else {
+                return Err(Error::from_raw(
+                    ErrorKind::TypeMismatch,
+                    format!("can't hash {}", union),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn hash_cell(
+    cell: &UnionCell,
+    hasher: &mut FnvHasher,
+    visiting: &mut Vec<*const Lock<Union>>,
+) -> Result<(), Error> {
+    match cell {
+        UnionCell::Owned(union) => hash_union(union, hasher, visiting),
+        UnionCell::Shared(lock) => {
+            let ptr = Shared::as_ptr(lock);
+
+            if visiting.contains(&ptr) {
+                return Err(Error::from_raw(
+                    ErrorKind::InvalidArgument,
+                    "can't hash a reference cycle",
+                ));
+            }
+
+            visiting.push(ptr);
+            let hashed = cell.map(|union| hash_union(union, hasher, visiting));
+            visiting.pop();
+
+            hashed
+        }
+    }
+}
+
+/// Stable hash of `union`, folded to fit a script `Int`.
+pub fn hash_value(union: &Union) -> Result<i32, Error> {
+    let mut hasher = FnvHasher::default();
+    hash_union(union, &mut hasher, &mut Vec::new())?;
+
+    let hash = hasher.finish();
+
+    Ok((hash ^ hash >> 32) as u32 as i32)
+}
+
+// Digests are returned as lowercase hex strings.
+def_module! {
+    pub mod hash {
+        fn "hash"(value: Union) {
+            hash_value(&value)
+        }
+
+        fn "crc32"(s: SharedString) {
+            format!("{:08x}", crc32(s.as_bytes()))
+        }
+
+        fn "crc32"(b: Blob) {
+            format!("{:08x}", crc32(&b))
+        }
+
+        fn "fnv1a"(s: SharedString) {
+            format!("{:016x}", fnv1a(s.as_bytes()))
+        }
+
+        fn "fnv1a"(b: Blob) {
+            format!("{:016x}", fnv1a(&b))
+        }
+
+        fn "sha256"(s: SharedString) {
+            hex(&sha256(s.as_bytes()))
+        }
+
+        fn "sha256"(b: Blob) {
+            hex(&sha256(&b))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn digests() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(
+            hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(&sha256(&[b'a'; 64])),
+            "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb"
+        );
+
+        assert_eq!(eval("std::hash::crc32(\"123456789\")"), "cbf43926");
+        assert_eq!(eval("std::hash::sha256(b\"\")"), hex(&sha256(b"")));
+    }
+
+    #[test]
+    fn stable_hash() {
+        // pinned so a change to the encoding doesn't go unnoticed
+        assert_eq!(eval("std::hash::hash([1, \"a\", 0.5])"), "1154165164");
+        assert_eq!(
+            eval("std::hash::hash(0.0) == std::hash::hash(-0.0)"),
+            "true"
+        );
+        assert_eq!(
+            eval("std::hash::hash(1) == std::hash::hash(\"1\")"),
+            "false"
+        );
+        assert_eq!(
+            eval("let a = [1, 2]; let b = [1, 2]; std::hash::hash(a) == std::hash::hash(b)"),
+            "true"
+        );
+        assert!(try_eval("std::hash::hash(some(1))").is_err());
+    }
+}
@@ -0,0 +1,431 @@
+use crate::closure::*;
+use crate::control_flow::*;
+use crate::error::*;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::span::*;
+use crate::variant::*;
+
+// Every adapter holds the iterator it wraps in a `UnionCell`, which is made shared
+// before it's advanced so the progress is kept between calls to `iter_next`.
+// Adapters are generic over anything with an `iter_next`, so host types implementing
+// the protocol get all of them for free.
+
+#[derive(Clone)]
+pub struct MapIter {
+    pub iter: UnionCell,
+    pub f: Closure,
+}
+
+#[derive(Clone)]
+pub struct FilterIter {
+    pub iter: UnionCell,
+    pub f: Closure,
+}
+
+#[derive(Clone)]
+pub struct EnumerateIter {
+    pub iter: UnionCell,
+    pub index: i32,
+}
+
+#[derive(Clone)]
+pub struct ZipIter {
+    pub a: UnionCell,
+    pub b: UnionCell,
+}
+
+#[derive(Clone)]
+pub struct TakeIter {
+    pub iter: UnionCell,
+    pub remaining: i32,
+}
+
+#[derive(Clone)]
+pub struct SkipIter {
+    pub iter: UnionCell,
+    pub remaining: i32,
+}
+
+#[derive(Clone)]
+pub struct ChainIter {
+    pub a: UnionCell,
+    pub b: UnionCell,
+    pub a_done: bool,
+}
+
+/// Reverses an iterator by buffering it when it's first advanced.
+#[derive(Clone)]
+pub struct RevIter {
+    pub iter: UnionCell,
+    pub buffer: Option<Vec<UnionCell>>,
+}
+
+#[derive(Clone)]
+pub struct StepByIter {
+    pub iter: UnionCell,
+    pub step: i32,
+    pub first: bool,
+}
+
+fn iter_of<T>(
+    runtime: &mut Runtime<T>,
+    scope: &mut Scope<T>,
+    value: Union,
+) -> Result<UnionCell, ControlFlow> {
+    let iter = runtime.into_iter(Variable::specified(value), scope)?;
+
+    Ok(iter.union.into_inner().into())
+}
+
+/// Gets a shared handle to the iterator stored in `adapter`.
+fn shared_iter<A: Variant, F>(adapter: &mut Mut<A>, mut get: F) -> UnionCell
+where
+    F: FnMut(&mut A) -> &mut UnionCell,
+{
+    adapter.map_mut(|a| get(a).get_shared())
+}
+
+fn predicate<T>(
+    runtime: &mut Runtime<T>,
+    scope: &mut Scope<T>,
+    f: &Closure,
+    item: Union,
+) -> Result<bool, ControlFlow> {
+    let returned = runtime.call_closure(f, vec![Variable::specified(item)], scope)?;
+
+    returned.map(Union::as_bool).ok_or_else(|| {
+        Error::new(ErrorKind::TypeMismatch, &runtime.source, f.body.span).into()
+    })
+}
+
+fn pair(a: Union, b: Union) -> Union {
+    Union::from(vec![UnionCell::from(a), UnionCell::from(b)])
+}
+
+def_module! {
+    pub mod iter {
+        fn "map"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union, f: Closure) {
+            Ok::<_, ControlFlow>(MapIter {
+                iter: iter_of(runtime, scope, iter)?,
+                f,
+            })
+        }
+
+        fn "iter_next"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, map: Mut<MapIter>) {
+            let mut iter = shared_iter(&mut map, |m| &mut m.iter);
+            let f = map.map(|m| m.f.clone());
+
+            match runtime.iter_next(&mut iter, scope)? {
+                Some(item) => {
+                    let mapped = runtime.call_closure(&f, vec![Variable::specified(item)], scope)?;
+
+                    Ok::<_, ControlFlow>(Some(mapped.into_inner()))
+                }
+                None => Ok(None),
+            }
+        }
+
+        fn "filter"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union, f: Closure) {
+            Ok::<_, ControlFlow>(FilterIter {
+                iter: iter_of(runtime, scope, iter)?,
+                f,
+            })
+        }
+
+        fn "iter_next"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, filter: Mut<FilterIter>) {
+            let mut iter = shared_iter(&mut filter, |f| &mut f.iter);
+            let f = filter.map(|f| f.f.clone());
+
+            while let Some(item) = runtime.iter_next(&mut iter, scope)? {
+                if predicate(runtime, scope, &f, item.clone())? {
+                    return Ok(Some(item));
+                }
+            }
+
+            Ok::<_, ControlFlow>(None)
+        }
+
+        fn "enumerate"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union) {
+            Ok::<_, ControlFlow>(EnumerateIter {
+                iter: iter_of(runtime, scope, iter)?,
+                index: 0,
+            })
+        }
+
+        fn "iter_next"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, enumerate: Mut<EnumerateIter>) {
+            let mut iter = shared_iter(&mut enumerate, |e| &mut e.iter);
+
+            match runtime.iter_next(&mut iter, scope)? {
+                Some(item) => {
+                    let index = enumerate.map_mut(|e| {
+                        e.index += 1;
+                        e.index - 1
+                    });
+
+                    Ok::<_, ControlFlow>(Some(pair(Union::from(index), item)))
+                }
+                None => Ok(None),
+            }
+        }
+
+        fn "zip"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, a: Union, b: Union) {
+            Ok::<_, ControlFlow>(ZipIter {
+                a: iter_of(runtime, scope, a)?,
+                b: iter_of(runtime, scope, b)?,
+            })
+        }
+
+        fn "iter_next"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, zip: Mut<ZipIter>) {
+            let mut a = shared_iter(&mut zip, |z| &mut z.a);
+            let mut b = shared_iter(&mut zip, |z| &mut z.b);
+
+            let a = match runtime.iter_next(&mut a, scope)? {
+                Some(a) => a,
+                None => return Ok(None),
+            };
+
+            match runtime.iter_next(&mut b, scope)? {
+                Some(b) => Ok::<_, ControlFlow>(Some(pair(a, b))),
+                None => Ok(None),
+            }
+        }
+
+        fn "take"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union, n: i32) {
+            Ok::<_, ControlFlow>(TakeIter {
+                iter: iter_of(runtime, scope, iter)?,
+                remaining: n,
+            })
+        }
+
+        fn "iter_next"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, take: Mut<TakeIter>) {
+            let mut iter = shared_iter(&mut take, |t| &mut t.iter);
+
+            let take_next = take.map_mut(|t| {
+                t.remaining -= 1;
+                t.remaining >= 0
+            });
+
+            if take_next {
+                runtime.iter_next(&mut iter, scope)
+            } else {
+                Ok(None)
+            }
+        }
+
+        fn "skip"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union, n: i32) {
+            Ok::<_, ControlFlow>(SkipIter {
+                iter: iter_of(runtime, scope, iter)?,
+                remaining: n,
+            })
+        }
+
+        fn "iter_next"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, skip: Mut<SkipIter>) {
+            let mut iter = shared_iter(&mut skip, |s| &mut s.iter);
+            let remaining = skip.map_mut(|s| std::mem::replace(&mut s.remaining, 0));
+
+            for _ in 0..remaining {
+                if runtime.iter_next(&mut iter, scope)?.is_none() {
+                    return Ok(None);
+                }
+            }
+
+            runtime.iter_next(&mut iter, scope)
+        }
+
+        fn "chain"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, a: Union, b: Union) {
+            Ok::<_, ControlFlow>(ChainIter {
+                a: iter_of(runtime, scope, a)?,
+                b: iter_of(runtime, scope, b)?,
+                a_done: false,
+            })
+        }
+
+        fn "iter_next"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, chain: Mut<ChainIter>) {
+            if !chain.map(|c| c.a_done) {
+                let mut a = shared_iter(&mut chain, |c| &mut c.a);
+
+                match runtime.iter_next(&mut a, scope)? {
+                    Some(item) => return Ok(Some(item)),
+                    None => chain.map_mut(|c| c.a_done = true),
+                }
+            }
+
+            let mut b = shared_iter(&mut chain, |c| &mut c.b);
+
+            runtime.iter_next(&mut b, scope)
+        }
+
+        fn "rev"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union) {
+            Ok::<_, ControlFlow>(RevIter {
+                iter: iter_of(runtime, scope, iter)?,
+                buffer: None,
+            })
+        }
+
+        fn "iter_next"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, rev: Mut<RevIter>) {
+            if rev.map(|r| r.buffer.is_none()) {
+                let mut iter = shared_iter(&mut rev, |r| &mut r.iter);
+                let mut buffer = Vec::new();
+
+                while let Some(item) = runtime.iter_next(&mut iter, scope)? {
+                    buffer.push(UnionCell::from(item));
+                }
+
+                let mut buffer = Some(buffer);
+                rev.map_mut(|r| r.buffer = buffer.take());
+            }
+
+            Ok::<_, ControlFlow>(rev.map_mut(|r| {
+                r.buffer.as_mut().and_then(Vec::pop).map(UnionCell::into_inner)
+            }))
+        }
+
+        fn "step_by"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union, step: i32) {
+            if step <= 0 {
+                return Err(Error::from_raw(
+                    ErrorKind::InvalidArgument,
+                    format!("step must be positive, got {}", step),
+                )
+                .into());
+            }
+
+            Ok::<_, ControlFlow>(StepByIter {
+                iter: iter_of(runtime, scope, iter)?,
+                step,
+                first: true,
+            })
+        }
+
+        fn "iter_next"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, step_by: Mut<StepByIter>) {
+            let mut iter = shared_iter(&mut step_by, |s| &mut s.iter);
+            let (step, first) = step_by.map_mut(|s| (s.step, std::mem::replace(&mut s.first, false)));
+
+            if !first {
+                for _ in 1..step {
+                    if runtime.iter_next(&mut iter, scope)?.is_none() {
+                        return Ok(None);
+                    }
+                }
+            }
+
+            runtime.iter_next(&mut iter, scope)
+        }
+
+        fn "fold"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union, init: Union, f: Closure) {
+            let mut iter = iter_of(runtime, scope, iter)?;
+            let mut acc = init;
+
+            while let Some(item) = runtime.iter_next(&mut iter, scope)? {
+                let input = vec![Variable::specified(acc), Variable::specified(item)];
+                acc = runtime.call_closure(&f, input, scope)?.into_inner();
+            }
+
+            Ok::<_, ControlFlow>(acc)
+        }
+
+        fn "sum"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union) {
+            let mut iter = iter_of(runtime, scope, iter)?;
+
+            let mut sum = match runtime.iter_next(&mut iter, scope)? {
+                Some(first) => first,
+                None => return Ok(Union::Int(0)),
+            };
+
+            while let Some(item) = runtime.iter_next(&mut iter, scope)? {
+                sum = runtime
+                    .eval_binop(
+                        "+",
+                        Span::new(0, 0),
+                        Variable::specified(sum),
+                        Variable::specified(item),
+                        scope,
+                    )?
+                    .into_inner();
+            }
+
+            Ok::<_, ControlFlow>(sum)
+        }
+
+        fn "count"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union) {
+            let mut iter = iter_of(runtime, scope, iter)?;
+            let mut count = 0;
+
+            while runtime.iter_next(&mut iter, scope)?.is_some() {
+                count += 1;
+            }
+
+            Ok::<_, ControlFlow>(count)
+        }
+
+        fn "any"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union, f: Closure) {
+            let mut iter = iter_of(runtime, scope, iter)?;
+
+            while let Some(item) = runtime.iter_next(&mut iter, scope)? {
+                if predicate(runtime, scope, &f, item)? {
+                    return Ok(true);
+                }
+            }
+
+            Ok::<_, ControlFlow>(false)
+        }
+
+        fn "all"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union, f: Closure) {
+            let mut iter = iter_of(runtime, scope, iter)?;
+
+            while let Some(item) = runtime.iter_next(&mut iter, scope)? {
+                if !predicate(runtime, scope, &f, item)? {
+                    return Ok(false);
+                }
+            }
+
+            Ok::<_, ControlFlow>(true)
+        }
+
+        fn "collect"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, iter: Union) {
+            let mut iter = iter_of(runtime, scope, iter)?;
+            let mut collected = Vec::new();
+
+            while let Some(item) = runtime.iter_next(&mut iter, scope)? {
+                collected.push(UnionCell::from(item));
+            }
+
+            Ok::<_, ControlFlow>(collected)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::prelude::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn adapters() {
+        assert_eq!(eval("[1, 2, 3, 4].map(|x| x * 2).filter(|x| x > 2).sum()"), "18");
+        assert_eq!(eval("[1, 2, 3].zip([4, 5]).map(|p| p[0] * p[1]).sum()"), "14");
+        assert_eq!(eval("[1, 2, 3].rev().collect().join(\",\")"), "3,2,1");
+        assert_eq!(
+            eval("[1, 2, 3, 4, 5].skip(1).step_by(2).chain([9]).collect().join(\",\")"),
+            "2,4,9"
+        );
+        assert_eq!(eval("[5, 6].enumerate().fold(0, |acc, p| acc + p[0] * p[1])"), "6");
+        assert_eq!(eval("[1, 2, 3].take(2).count()"), "2");
+        assert_eq!(eval("[1, 2, 3].any(|x| x == 2)"), "true");
+        assert_eq!(eval("[1, 2, 3].all(|x| x > 1)"), "false");
+    }
+
+    #[test]
+    fn adapters_are_lazy() {
+        let engine = Engine::<()>::new();
+
+        let calls = engine
+            .eval(
+                &mut (),
+                "let calls = 0; let it = [1, 2, 3].map(|x| { calls += 1; x }); it.take(1).count(); calls",
+            )
+            .unwrap();
+
+        assert_eq!(calls.to_string(), "1");
+    }
+}
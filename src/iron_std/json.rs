@@ -0,0 +1,42 @@
+use crate::json::*;
+use crate::variant::*;
+
+def_module! {
+    pub mod json {
+        fn "parse"(source: SharedString) {
+            Json::parse(&source).map(Json::into_union)
+        }
+
+        fn "to_json"(value: Union) {
+            Json::from_union(&value).map(|json| json.to_string(false))
+        }
+
+        fn "to_json"(value: Union, pretty: bool) {
+            Json::from_union(&value).map(|json| json.to_string(pretty))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::*;
+
+    #[test]
+    fn parse_and_serialize() {
+        assert_eq!(
+            eval(r#"let v = std::json::parse("[10, 2.5, {}]"); v[0] + v.len()"#),
+            "13"
+        );
+        assert_eq!(
+            eval(
+                r#"let m = map(); m.insert("a", [1, true, std::json::parse("null")]); std::json::to_json(m)"#
+            ),
+            r#"{"a":[1,true,null]}"#
+        );
+        assert_eq!(eval(r#"std::json::to_json([], true)"#), "[]");
+
+        assert!(try_eval(r#"std::json::parse("{")"#).is_err());
+        assert!(try_eval("std::json::to_json(1..2)").is_err());
+        assert!(try_eval("let a = [1]; a.push(&a); std::json::to_json(a)").is_err());
+    }
+}
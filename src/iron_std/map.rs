@@ -0,0 +1,71 @@
+use crate::error::*;
+use crate::iron_std::ArrayIter;
+use crate::variant::*;
+
+fn missing_key(key: &str) -> Error {
+    Error::from_raw(ErrorKind::InvalidArgument, format!("no key {:?}", key))
+}
+
+def_module! {
+    pub mod map {
+        fn "map"() {
+            Map::new()
+        }
+
+        fn "insert"(map: &mut Map, key: SharedString, value: Union) {
+            map.insert(key.to_string(), UnionCell::from(value))
+                .map(UnionCell::into_inner)
+        }
+
+        fn "get"(map: &mut Map, key: SharedString) {
+            map.get(key.as_str()).map(UnionCell::cloned)
+        }
+
+        fn "remove"(map: &mut Map, key: SharedString) {
+            map.remove(key.as_str()).map(UnionCell::into_inner)
+        }
+
+        fn "contains_key"(map: &mut Map, key: SharedString) {
+            map.contains_key(key.as_str())
+        }
+
+        fn "keys"(map: &mut Map) {
+            map.keys()
+                .map(|key| UnionCell::new(key.clone()))
+                .collect::<Vec<_>>()
+        }
+
+        fn "values"(map: &mut Map) {
+            map.values().cloned().collect::<Vec<_>>()
+        }
+
+        fn "len"(map: &mut Map) {
+            map.len() as i32
+        }
+
+        fn "is_empty"(map: &mut Map) {
+            map.is_empty()
+        }
+
+        // only existing keys can be indexed, new ones are added with `insert`
+        fn "[]"(map: Mut<Map>, key: SharedString) {
+            map.map_mut(|map| match map.get_mut(key.as_str()) {
+                Some(value) => Ok(value.get_shared()),
+                None => Err(missing_key(&key)),
+            })
+        }
+
+        // iterates `[key, value]` pairs in key order
+        fn "into_iter"(map: Map) {
+            ArrayIter {
+                items: map
+                    .into_iter()
+                    .map(|(key, value)| {
+                        UnionCell::new(vec![UnionCell::new(key), value])
+                    })
+                    .collect(),
+                position: 0,
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use crate::error::*;
+
+fn overflow(op: &str) -> Error {
+    Error::from_raw(ErrorKind::InvalidArgument, format!("{} overflowed", op))
+}
+
+fn check_bounds<N: PartialOrd + std::fmt::Display>(min: N, max: N) -> Result<(), Error> {
+    if min <= max {
+        Ok(())
+    } else {
+        Err(Error::from_raw(
+            ErrorKind::InvalidArgument,
+            format!("clamp bounds {} > {}", min, max),
+        ))
+    }
+}
+
+// Functions taking two numbers are registered for every mix of `Int` and `Float`,
+// a mix always computes in `Float`. So are the bounds of `clamp` and the ends of
+// `lerp`, whose `t` is always a `Float`.
+fn clamp_float(x: f32, min: f32, max: f32) -> Result<f32, Error> {
+    check_bounds(min, max).map(|_| x.clamp(min, max))
+}
+
+def_module! {
+    pub mod math {
+        fn "PI"() {
+            std::f32::consts::PI
+        }
+
+        fn "E"() {
+            std::f32::consts::E
+        }
+
+        fn "sqrt"(x: i32) {
+            (x as f32).sqrt()
+        }
+
+        fn "sqrt"(x: f32) {
+            x.sqrt()
+        }
+
+        fn "pow"(base: i32, exp: i32) {
+            if exp < 0 {
+                return Err(Error::from_raw(
+                    ErrorKind::InvalidArgument,
+                    format!("negative exponent {} for an Int base", exp),
+                ));
+            }
+
+            base.checked_pow(exp as u32).ok_or_else(|| overflow("pow"))
+        }
+
+        fn "pow"(base: f32, exp: f32) {
+            base.powf(exp)
+        }
+
+        fn "pow"(base: f32, exp: i32) {
+            base.powi(exp)
+        }
+
+        fn "pow"(base: i32, exp: f32) {
+            (base as f32).powf(exp)
+        }
+
+        fn "abs"(x: i32) {
+            x.checked_abs().ok_or_else(|| overflow("abs"))
+        }
+
+        fn "abs"(x: f32) {
+            x.abs()
+        }
+
+        fn "min"(a: i32, b: i32) {
+            a.min(b)
+        }
+
+        fn "min"(a: f32, b: f32) {
+            a.min(b)
+        }
+
+        fn "min"(a: i32, b: f32) {
+            (a as f32).min(b)
+        }
+
+        fn "min"(a: f32, b: i32) {
+            a.min(b as f32)
+        }
+
+        fn "max"(a: i32, b: i32) {
+            a.max(b)
+        }
+
+        fn "max"(a: f32, b: f32) {
+            a.max(b)
+        }
+
+        fn "max"(a: i32, b: f32) {
+            (a as f32).max(b)
+        }
+
+        fn "max"(a: f32, b: i32) {
+            a.max(b as f32)
+        }
+
+        fn "clamp"(x: i32, min: i32, max: i32) {
+            check_bounds(min, max).map(|_| x.clamp(min, max))
+        }
+
+        fn "clamp"(x: f32, min: f32, max: f32) {
+            check_bounds(min, max).map(|_| x.clamp(min, max))
+        }
+
+        fn "clamp"(x: i32, min: i32, max: f32) {
+            clamp_float(x as f32, min as f32, max)
+        }
+
+        fn "clamp"(x: i32, min: f32, max: i32) {
+            clamp_float(x as f32, min, max as f32)
+        }
+
+        fn "clamp"(x: i32, min: f32, max: f32) {
+            clamp_float(x as f32, min, max)
+        }
+
+        fn "clamp"(x: f32, min: i32, max: i32) {
+            clamp_float(x, min as f32, max as f32)
+        }
+
+        fn "clamp"(x: f32, min: i32, max: f32) {
+            clamp_float(x, min as f32, max)
+        }
+
+        fn "clamp"(x: f32, min: f32, max: i32) {
+            clamp_float(x, min, max as f32)
+        }
+
+        fn "floor"(x: i32) {
+            x
+        }
+
+        fn "floor"(x: f32) {
+            x.floor()
+        }
+
+        fn "ceil"(x: i32) {
+            x
+        }
+
+        fn "ceil"(x: f32) {
+            x.ceil()
+        }
+
+        fn "round"(x: i32) {
+            x
+        }
+
+        fn "round"(x: f32) {
+            x.round()
+        }
+
+        fn "sin"(x: i32) {
+            (x as f32).sin()
+        }
+
+        fn "sin"(x: f32) {
+            x.sin()
+        }
+
+        fn "cos"(x: i32) {
+            (x as f32).cos()
+        }
+
+        fn "cos"(x: f32) {
+            x.cos()
+        }
+
+        fn "tan"(x: i32) {
+            (x as f32).tan()
+        }
+
+        fn "tan"(x: f32) {
+            x.tan()
+        }
+
+        fn "atan2"(y: i32, x: i32) {
+            (y as f32).atan2(x as f32)
+        }
+
+        fn "atan2"(y: f32, x: f32) {
+            y.atan2(x)
+        }
+
+        fn "atan2"(y: i32, x: f32) {
+            (y as f32).atan2(x)
+        }
+
+        fn "atan2"(y: f32, x: i32) {
+            y.atan2(x as f32)
+        }
+
+        fn "exp"(x: i32) {
+            (x as f32).exp()
+        }
+
+        fn "exp"(x: f32) {
+            x.exp()
+        }
+
+        fn "ln"(x: i32) {
+            (x as f32).ln()
+        }
+
+        fn "ln"(x: f32) {
+            x.ln()
+        }
+
+        fn "log10"(x: i32) {
+            (x as f32).log10()
+        }
+
+        fn "log10"(x: f32) {
+            x.log10()
+        }
+
+        fn "is_nan"(_x: i32) {
+            false
+        }
+
+        fn "is_nan"(x: f32) {
+            x.is_nan()
+        }
+
+        fn "lerp"(a: i32, b: i32, t: f32) {
+            a as f32 + (b as f32 - a as f32) * t
+        }
+
+        fn "lerp"(a: f32, b: f32, t: f32) {
+            a + (b - a) * t
+        }
+
+        fn "lerp"(a: i32, b: f32, t: f32) {
+            a as f32 + (b - a as f32) * t
+        }
+
+        fn "lerp"(a: f32, b: i32, t: f32) {
+            a + (b as f32 - a) * t
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::*;
+
+    #[test]
+    fn overloads() {
+        assert_eq!(eval("std::math::abs(-3)"), "3");
+        assert_eq!(eval("std::math::abs(-1.5)"), "1.5");
+        assert_eq!(eval("std::math::pow(2, 10)"), "1024");
+        assert_eq!(eval("std::math::pow(4.0, 0.5)"), "2");
+        assert_eq!(eval("std::math::max(1, 2.5)"), "2.5");
+        assert_eq!(eval("std::math::clamp(12, 0, 10)"), "10");
+        assert_eq!(eval("std::math::round(2.5)"), "3");
+        assert_eq!(eval("std::math::lerp(0, 10, 0.25)"), "2.5");
+        assert_eq!(eval("std::math::lerp(0, 10.0, 0.5)"), "5");
+        assert_eq!(eval("std::math::clamp(12, 0, 10.5)"), "10.5");
+        assert_eq!(eval("std::math::clamp(-0.5, 0, 10)"), "0");
+        assert_eq!(eval("std::math::floor(std::math::PI)"), "3");
+        assert_eq!(eval("std::math::is_nan(std::math::sqrt(-1.0))"), "true");
+
+        assert!(try_eval("std::math::pow(2, 40)").is_err());
+        assert!(try_eval("std::math::clamp(1, 10, 0)").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::closure::*;
+use crate::control_flow::*;
+use crate::error::*;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::variant::*;
+
+/// What `ok(..)` and `err(..)` create in scripts.
+pub type ScriptResult = Result<Union, Union>;
+
+fn call<T>(
+    runtime: &mut Runtime<T>,
+    scope: &mut Scope<T>,
+    f: &Closure,
+    value: Union,
+) -> Result<Union, ControlFlow> {
+    runtime
+        .call_closure(f, vec![Variable::specified(value)], scope)
+        .map(Variable::into_inner)
+}
+
+fn unwrap_failed(message: impl Into<String>) -> Error {
+    Error::from_raw(ErrorKind::Unwrap, message)
+}
+
+/// Checks the value returned by an `and_then` closure has the type `and_then` works on.
+fn expect_type<V: Variant>(returned: Union, name: &str) -> Result<V, Error> {
+    let ty = returned.ty();
+
+    returned.downcast::<V>().ok_or_else(|| {
+        Error::from_raw(
+            ErrorKind::TypeMismatch,
+            format!("and_then closure returned {} instead of {}", ty, name),
+        )
+    })
+}
+
+def_module! {
+    pub mod option {
+        fn "some"(s: Union) {
+            Some(s)
+        }
+
+        fn "none"() {
+            None::<Union>
+        }
+
+        fn "is_some"(o: Option<Union>) {
+            o.is_some()
+        }
+
+        fn "is_none"(o: Option<Union>) {
+            o.is_none()
+        }
+
+        fn "unwrap"(o: Option<Union>) {
+            o.ok_or_else(|| unwrap_failed("called unwrap on none"))
+        }
+
+        fn "expect"(o: Option<Union>, message: SharedString) {
+            o.ok_or_else(|| unwrap_failed(message.to_string()))
+        }
+
+        fn "unwrap_or"(o: Option<Union>, default: Union) {
+            o.unwrap_or(default)
+        }
+
+        fn "unwrap_or_else"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, o: Option<Union>, f: Closure) {
+            match o {
+                Some(value) => Ok(value),
+                None => runtime
+                    .call_closure(&f, Vec::new(), scope)
+                    .map(Variable::into_inner),
+            }
+        }
+
+        fn "map"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, o: Option<Union>, f: Closure) {
+            o.map(|value| call(runtime, scope, &f, value)).transpose()
+        }
+
+        fn "and_then"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, o: Option<Union>, f: Closure) {
+            match o {
+                Some(value) => {
+                    let returned = call(runtime, scope, &f, value)?;
+
+                    Ok(expect_type::<Option<Union>>(returned, "an option")?)
+                }
+                None => Ok::<_, ControlFlow>(None),
+            }
+        }
+
+        fn "or"(o: Option<Union>, other: Option<Union>) {
+            o.or(other)
+        }
+
+        fn "ok_or"(o: Option<Union>, err: Union) {
+            o.ok_or(err)
+        }
+    }
+}
+
+def_module! {
+    pub mod result {
+        fn "ok"(value: Union) {
+            ScriptResult::Ok(value)
+        }
+
+        fn "err"(err: Union) {
+            ScriptResult::Err(err)
+        }
+
+        fn "is_ok"(r: ScriptResult) {
+            r.is_ok()
+        }
+
+        fn "is_err"(r: ScriptResult) {
+            r.is_err()
+        }
+
+        fn "unwrap"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, r: ScriptResult) {
+            match r {
+                Ok(value) => Ok::<_, ControlFlow>(value),
+                Err(err) => {
+                    let err = runtime.debug(&err, false, scope)?;
+
+                    Err(unwrap_failed(format!("called unwrap on err({})", err)).into())
+                }
+            }
+        }
+
+        fn "unwrap_err"(r: ScriptResult) {
+            match r {
+                Ok(_) => Err(unwrap_failed("called unwrap_err on ok")),
+                Err(err) => Ok(err),
+            }
+        }
+
+        fn "expect"(r: ScriptResult, message: SharedString) {
+            r.map_err(|_| unwrap_failed(message.to_string()))
+        }
+
+        fn "unwrap_or"(r: ScriptResult, default: Union) {
+            r.unwrap_or(default)
+        }
+
+        fn "unwrap_or_else"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, r: ScriptResult, f: Closure) {
+            match r {
+                Ok(value) => Ok(value),
+                Err(err) => call(runtime, scope, &f, err),
+            }
+        }
+
+        fn "map"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, r: ScriptResult, f: Closure) {
+            match r {
+                Ok(value) => Ok(ScriptResult::Ok(call(runtime, scope, &f, value)?)),
+                Err(err) => Ok::<_, ControlFlow>(ScriptResult::Err(err)),
+            }
+        }
+
+        fn "map_err"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, r: ScriptResult, f: Closure) {
+            match r {
+                Ok(value) => Ok::<_, ControlFlow>(ScriptResult::Ok(value)),
+                Err(err) => Ok(ScriptResult::Err(call(runtime, scope, &f, err)?)),
+            }
+        }
+
+        fn "and_then"(runtime: &mut Runtime<T>, scope: &mut Scope<T>, r: ScriptResult, f: Closure) {
+            match r {
+                Ok(value) => {
+                    let returned = call(runtime, scope, &f, value)?;
+
+                    Ok(expect_type::<ScriptResult>(returned, "a result")?)
+                }
+                Err(err) => Ok::<_, ControlFlow>(ScriptResult::Err(err)),
+            }
+        }
+
+        fn "ok"(r: ScriptResult) {
+            r.ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn combinators() {
+        assert_eq!(eval("some(2).map(|x| x * 3).unwrap()"), "6");
+        assert_eq!(eval("none().map(|x| x * 3).unwrap_or(1)"), "1");
+        assert_eq!(eval("some(2).and_then(|x| none()).is_none()"), "true");
+        assert_eq!(eval("none().unwrap_or_else(|| 5)"), "5");
+        assert_eq!(eval("none().ok_or(\"missing\").unwrap_err()"), "missing");
+        assert_eq!(eval("ok(1).and_then(|x| err(x + 1)).unwrap_err()"), "2");
+        assert_eq!(
+            eval("err(1).map_err(|e| e * 10).unwrap_or_else(|e| e + 1)"),
+            "11"
+        );
+        assert_eq!(eval("ok(4).map(|x| x / 2).to_string()"), "Ok(2)");
+
+        let error = try_eval("err(\"bad\").unwrap()").unwrap_err();
+
+        assert!(matches!(error.kind, ErrorKind::Unwrap));
+        assert!(error.code.contains("\"bad\""));
+        assert!(try_eval("none().expect(\"needed\")").is_err());
+    }
+
+    #[test]
+    fn try_operator() {
+        let source = "
+            fn half(x) {
+                if x % 2 == 1 {
+                    err(\"odd\")
+                } else {
+                    ok(x / 2)
+                }
+            }
+
+            fn quarter(x) {
+                let h = half(x)?;
+                ok(half(h)?)
+            }
+
+            fn last_plus_one(arr) {
+                let x = arr.pop()?;
+                some(x + 1)
+            }
+
+            [quarter(8).unwrap(), quarter(6).unwrap_err(), last_plus_one([]).is_none()].to_string()
+        ";
+
+        assert_eq!(eval(source), "[2, \"odd\", true]");
+        // `try` catches errors, not the early return of `?`
+        assert_eq!(
+            eval("fn f(x) { try { let y = x?; } catch { } ok(5) } f(err(1)).unwrap_err()"),
+            "1"
+        );
+        assert!(try_eval("1?").is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use crate::error::*;
+use crate::rng::*;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::variant::*;
+
+fn choose(rng: &mut Rng, arr: &[UnionCell]) -> Option<Union> {
+    if arr.is_empty() {
+        None
+    } else {
+        let index = rng.below(arr.len() as u64) as usize;
+
+        Some(arr[index].clone().into_inner())
+    }
+}
+
+// The generator lives on the `Runtime`, seeded with `Engine::set_seed`, so a run can
+// be replayed by running it again with the same seed.
+def_module! {
+    pub mod rand {
+        fn "seed"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, seed: i32) {
+            runtime.rng = Rng::new(seed as u64);
+        }
+
+        // both bounds are inclusive, like a dice roll `rand_int(1, 6)`
+        fn "rand_int"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, lo: i32, hi: i32) {
+            if lo > hi {
+                return Err(Error::from_raw(
+                    ErrorKind::InvalidArgument,
+                    format!("rand_int bounds {} > {}", lo, hi),
+                ));
+            }
+
+            Ok(runtime.rng.int_in(lo, hi))
+        }
+
+        fn "rand_float"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>) {
+            runtime.rng.float()
+        }
+
+        fn "choose"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, arr: Mut<Vec<UnionCell>>) {
+            arr.map(|arr| choose(&mut runtime.rng, arr))
+        }
+
+        fn "choose"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, arr: Vec<UnionCell>) {
+            choose(&mut runtime.rng, &arr)
+        }
+
+        fn "shuffle"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, arr: Mut<Vec<UnionCell>>) {
+            arr.map_mut(|arr| runtime.rng.shuffle(arr));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::*;
+
+    #[test]
+    fn seeded() {
+        let mut engine = Engine::<()>::new();
+        engine.set_seed(7);
+
+        let source = "
+            let arr = [1, 2, 3, 4, 5];
+            std::rand::shuffle(&arr);
+            arr.push(std::rand::rand_int(1, 6));
+            arr.push(std::rand::choose(&arr).unwrap());
+            arr.join(\",\")
+        ";
+
+        let first = engine.eval(&mut (), source).unwrap().to_string();
+
+        assert_eq!(engine.eval(&mut (), source).unwrap().to_string(), first);
+
+        engine.set_seed(8);
+        assert_ne!(engine.eval(&mut (), source).unwrap().to_string(), first);
+
+        assert!(engine.eval(&mut (), "std::rand::rand_int(2, 1)").is_err());
+        assert_eq!(
+            engine
+                .eval(&mut (), "std::rand::choose([4]).unwrap()")
+                .unwrap()
+                .to_string(),
+            "4"
+        );
+        assert_eq!(
+            engine
+                .eval(&mut (), "let a = [1, 2]; std::rand::choose(a).unwrap() < 3")
+                .unwrap()
+                .to_string(),
+            "true"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::error::*;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::variant::*;
+use ::regex::Regex;
+use fnv::FnvHashMap;
+use std::sync::{Arc, Mutex};
+
+/// Patterns compiled by `regex(..)`, the cache is cleared once it holds this many.
+const MAX_CACHED: usize = 256;
+
+/// Compiled patterns shared by every run of an engine, so a script compiling the same
+/// pattern in a loop only pays for it once.
+#[derive(Clone, Debug, Default)]
+pub struct RegexCache {
+    patterns: Arc<Mutex<FnvHashMap<String, Regex>>>,
+}
+
+impl RegexCache {
+    pub fn get(&self, pattern: &str) -> Result<Regex, Error> {
+        let mut patterns = self.patterns.lock().unwrap();
+
+        if let Some(regex) = patterns.get(pattern) {
+            return Ok(regex.clone());
+        }
+
+        let regex = Regex::new(pattern)
+            .map_err(|err| Error::from_raw(ErrorKind::InvalidArgument, err.to_string()))?;
+
+        if patterns.len() >= MAX_CACHED {
+            patterns.clear();
+        }
+
+        patterns.insert(pattern.to_string(), regex.clone());
+
+        Ok(regex)
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn str_array<'a>(iter: impl Iterator<Item = &'a str>) -> Vec<UnionCell> {
+    iter.map(|s| UnionCell::new(s.to_string())).collect()
+}
+
+def_module! {
+    pub mod regex {
+        fn "regex"(runtime: &mut Runtime<T>, _scope: &mut Scope<T>, pattern: SharedString) {
+            runtime.regex_cache.get(&pattern)
+        }
+
+        fn "is_match"(regex: Regex, s: SharedString) {
+            regex.is_match(&s)
+        }
+
+        fn "find"(regex: Regex, s: SharedString) {
+            regex.find(&s).map(|found| Union::from(found.as_str().to_string()))
+        }
+
+        fn "find_all"(regex: Regex, s: SharedString) {
+            str_array(regex.find_iter(&s).map(|found| found.as_str()))
+        }
+
+        // group 0 is the whole match, groups that didn't participate are `()`
+        fn "captures"(regex: Regex, s: SharedString) {
+            regex.captures(&s).map(|captures| {
+                let groups = captures
+                    .iter()
+                    .map(|group| match group {
+                        Some(group) => UnionCell::new(group.as_str().to_string()),
+                        None => UnionCell::new(()),
+                    })
+                    .collect::<Vec<_>>();
+
+                Union::from(groups)
+            })
+        }
+
+        fn "named_captures"(regex: Regex, s: SharedString) {
+            regex.captures(&s).map(|captures| {
+                let groups = regex
+                    .capture_names()
+                    .flatten()
+                    .filter_map(|name| {
+                        captures
+                            .name(name)
+                            .map(|group| (name.to_string(), UnionCell::new(group.as_str().to_string())))
+                    })
+                    .collect::<Map>();
+
+                Union::from(groups)
+            })
+        }
+
+        // `$1` and `$name` in the replacement refer to groups
+        fn "replace"(regex: Regex, s: SharedString, replacement: SharedString) {
+            regex.replace(&s, replacement.as_str()).into_owned()
+        }
+
+        fn "replace_all"(regex: Regex, s: SharedString, replacement: SharedString) {
+            regex.replace_all(&s, replacement.as_str()).into_owned()
+        }
+
+        fn "split"(regex: Regex, s: SharedString) {
+            str_array(regex.split(&s))
+        }
+
+        fn "display"(regex: Regex) {
+            regex.as_str().to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn methods() {
+        assert_eq!(eval(r#"regex("^a+$").is_match("aaa")"#), "true");
+        assert_eq!(eval(r#"regex("[0-9]+").find("ab 12 34").unwrap()"#), "12");
+        assert_eq!(
+            eval(r#"regex("[0-9]+").find_all("1 22 333").join(",")"#),
+            "1,22,333"
+        );
+        assert_eq!(
+            eval(r#"regex("(a)(x)?(b)").captures("ab").unwrap()[3]"#),
+            "b"
+        );
+        assert_eq!(
+            eval(
+                r#"regex("(?P<key>[a-z]+)=(?P<value>[0-9]+)").named_captures("hp=10").unwrap()["value"]"#
+            ),
+            "10"
+        );
+        assert_eq!(
+            eval(r#"regex("([a-z]+)@").replace_all("a@ b@", "<$1>")"#),
+            "<a> <b>"
+        );
+        assert_eq!(
+            eval(r#"std::regex::regex(", *").split("a,b,  c").join("|")"#),
+            "a|b|c"
+        );
+
+        assert!(try_eval(r#"regex("(")"#).is_err());
+    }
+
+    #[test]
+    fn cached() {
+        let engine = Engine::<()>::new();
+
+        engine
+            .eval(
+                &mut (),
+                r#"for i in 0..10 { regex("a+").is_match("a"); regex("b+"); }"#,
+            )
+            .unwrap();
+
+        assert_eq!(engine.regex_cache().len(), 2);
+    }
+}
@@ -0,0 +1,528 @@
+use crate::error::*;
+use crate::variant::*;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A point in time with a fixed UTC offset, two [`DateTime`]s are equal if they're the
+/// same instant regardless of their offsets.
+#[derive(Clone, Copy, Debug)]
+pub struct DateTime {
+    /// Seconds since the unix epoch in UTC.
+    pub secs: i64,
+    pub nanos: u32,
+    /// Seconds east of UTC, only used when formatting.
+    pub offset: i32,
+}
+
+impl PartialEq for DateTime {
+    fn eq(&self, other: &Self) -> bool {
+        (self.secs, self.nanos) == (other.secs, other.nanos)
+    }
+}
+
+impl PartialOrd for DateTime {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        (self.secs, self.nanos).partial_cmp(&(other.secs, other.nanos))
+    }
+}
+
+/// Days since the unix epoch of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Inverse of [`days_from_civil`], returns `(year, month, day)`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn time_error(message: impl std::fmt::Display) -> Error {
+    Error::from_raw(ErrorKind::InvalidArgument, format!("time: {}", message))
+}
+
+impl DateTime {
+    pub fn now() -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Self {
+            secs: since_epoch.as_secs() as i64,
+            nanos: since_epoch.subsec_nanos(),
+            offset: 0,
+        }
+    }
+
+    pub fn from_timestamp(secs: i64) -> Self {
+        Self {
+            secs,
+            nanos: 0,
+            offset: 0,
+        }
+    }
+
+    /// Local seconds, the offset applied.
+    fn local_secs(&self) -> i64 {
+        self.secs + self.offset as i64
+    }
+
+    /// `(year, month, day, hour, minute, second)` in the offset's local time.
+    pub fn fields(&self) -> (i64, u32, u32, u32, u32, u32) {
+        let local = self.local_secs();
+        let (year, month, day) = civil_from_days(local.div_euclid(86_400));
+        let secs_of_day = local.rem_euclid(86_400) as u32;
+
+        (
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60,
+        )
+    }
+
+    /// Parses an ISO-8601 timestamp like `2024-05-01T12:30:00.5+02:00`, the time can be
+    /// left out for midnight and the offset for UTC.
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let invalid = || time_error(format!("invalid ISO-8601 timestamp {:?}", s));
+        let number = |s: Option<&str>, digits: usize| {
+            s.filter(|s| s.len() == digits && s.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|s| s.parse::<u32>().ok())
+                .ok_or_else(invalid)
+        };
+
+        let (date, time) = match s.find(['T', 't', ' ']) {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+
+        let mut date_parts = date.split('-');
+        let year = number(date_parts.next(), 4)?;
+        let month = number(date_parts.next(), 2)?;
+        let day = number(date_parts.next(), 2)?;
+
+        if date_parts.next().is_some()
+            || !(1..=12).contains(&month)
+            || day == 0
+            || day > days_in_month(year as i64, month)
+        {
+            return Err(invalid());
+        }
+
+        let (mut hour, mut minute, mut second, mut nanos, mut offset) = (0, 0, 0, 0, 0);
+
+        if let Some(time) = time {
+            let (clock, zone) = match time.find(['Z', 'z', '+', '-']) {
+                Some(i) => (&time[..i], &time[i..]),
+                None => (time, "Z"),
+            };
+
+            let (clock, fraction) = match clock.find('.') {
+                Some(i) => (&clock[..i], Some(&clock[i + 1..])),
+                None => (clock, None),
+            };
+
+            let mut clock_parts = clock.split(':');
+            hour = number(clock_parts.next(), 2)?;
+            minute = number(clock_parts.next(), 2)?;
+            second = number(clock_parts.next(), 2)?;
+
+            if clock_parts.next().is_some() || hour > 23 || minute > 59 || second > 59 {
+                return Err(invalid());
+            }
+
+            if let Some(fraction) = fraction {
+                if fraction.is_empty() || fraction.len() > 9 {
+                    return Err(invalid());
+                }
+
+                nanos =
+                    number(Some(fraction), fraction.len())? * 10u32.pow(9 - fraction.len() as u32);
+            }
+
+            offset = match zone {
+                "Z" | "z" => 0,
+                _ => {
+                    let sign = if zone.starts_with('-') { -1 } else { 1 };
+                    let mut zone_parts = zone[1..].split(':');
+                    let hours = number(zone_parts.next(), 2)? as i32;
+                    let minutes = number(zone_parts.next(), 2)? as i32;
+
+                    if zone_parts.next().is_some() || hours > 23 || minutes > 59 {
+                        return Err(invalid());
+                    }
+
+                    sign * (hours * 3600 + minutes * 60)
+                }
+            };
+        }
+
+        let local = days_from_civil(year as i64, month, day) * 86_400
+            + (hour * 3600 + minute * 60 + second) as i64;
+
+        Ok(Self {
+            secs: local - offset as i64,
+            nanos,
+            offset,
+        })
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        let nanos = self.nanos + duration.subsec_nanos();
+        let secs = self
+            .secs
+            .checked_add(i64::try_from(duration.as_secs()).ok()?)?
+            .checked_add((nanos / 1_000_000_000) as i64)?;
+
+        Some(Self {
+            secs,
+            nanos: nanos % 1_000_000_000,
+            offset: self.offset,
+        })
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        let mut secs = self
+            .secs
+            .checked_sub(i64::try_from(duration.as_secs()).ok()?)?;
+        let mut nanos = self.nanos as i64 - duration.subsec_nanos() as i64;
+
+        if nanos < 0 {
+            secs = secs.checked_sub(1)?;
+            nanos += 1_000_000_000;
+        }
+
+        Some(Self {
+            secs,
+            nanos: nanos as u32,
+            offset: self.offset,
+        })
+    }
+
+    /// Time from `earlier` to `self`, [`None`] if `earlier` is later.
+    pub fn since(&self, earlier: &Self) -> Option<Duration> {
+        let mut secs = self.secs - earlier.secs;
+        let mut nanos = self.nanos as i64 - earlier.nanos as i64;
+
+        if nanos < 0 {
+            secs -= 1;
+            nanos += 1_000_000_000;
+        }
+
+        if secs < 0 {
+            None
+        } else {
+            Some(Duration::new(secs as u64, nanos as u32))
+        }
+    }
+}
+
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (year, month, day, hour, minute, second) = self.fields();
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year, month, day, hour, minute, second
+        )?;
+
+        if self.nanos != 0 {
+            let fraction = format!("{:09}", self.nanos);
+            write!(f, ".{}", fraction.trim_end_matches('0'))?;
+        }
+
+        if self.offset == 0 {
+            write!(f, "Z")
+        } else {
+            let sign = if self.offset < 0 { '-' } else { '+' };
+            let offset = self.offset.abs();
+
+            write!(f, "{}{:02}:{:02}", sign, offset / 3600, offset / 60 % 60)
+        }
+    }
+}
+
+fn overflow() -> Error {
+    time_error("overflow")
+}
+
+fn to_int(value: u128) -> Result<i32, Error> {
+    i32::try_from(value).map_err(|_| overflow())
+}
+
+fn from_secs(secs: f32) -> Result<Duration, Error> {
+    Duration::try_from_secs_f32(secs).map_err(|_| time_error(format!("invalid duration {}s", secs)))
+}
+
+fn non_negative(value: i32) -> Result<u64, Error> {
+    u64::try_from(value).map_err(|_| time_error(format!("negative duration {}", value)))
+}
+
+// Constructors live under `std::time`, methods and operators on the types are global
+// so they work with method calls and binary operators.
+def_module! {
+    pub mod time {
+        fn "now"() {
+            Instant::now()
+        }
+
+        fn "utc_now"() {
+            DateTime::now()
+        }
+
+        fn "from_timestamp"(secs: i32) {
+            DateTime::from_timestamp(secs as i64)
+        }
+
+        fn "parse"(s: SharedString) {
+            DateTime::parse(&s)
+        }
+
+        fn "secs"(secs: i32) {
+            non_negative(secs).map(Duration::from_secs)
+        }
+
+        fn "secs"(secs: f32) {
+            from_secs(secs)
+        }
+
+        fn "millis"(millis: i32) {
+            non_negative(millis).map(Duration::from_millis)
+        }
+    }
+}
+
+def_module! {
+    pub mod time_ops {
+        fn "elapsed"(instant: Instant) {
+            instant.elapsed()
+        }
+
+        fn "as_secs"(duration: Duration) {
+            to_int(duration.as_secs() as u128)
+        }
+
+        fn "as_millis"(duration: Duration) {
+            to_int(duration.as_millis())
+        }
+
+        fn "as_secs_f"(duration: Duration) {
+            duration.as_secs_f32()
+        }
+
+        fn "timestamp"(date_time: DateTime) {
+            i32::try_from(date_time.secs).map_err(|_| overflow())
+        }
+
+        fn "with_offset"(date_time: DateTime, minutes: i32) {
+            if minutes.abs() >= 24 * 60 {
+                return Err(time_error(format!("offset of {} minutes", minutes)));
+            }
+
+            Ok(DateTime {
+                offset: minutes * 60,
+                ..date_time
+            })
+        }
+
+        fn "year"(date_time: DateTime) {
+            date_time.fields().0 as i32
+        }
+
+        fn "month"(date_time: DateTime) {
+            date_time.fields().1 as i32
+        }
+
+        fn "day"(date_time: DateTime) {
+            date_time.fields().2 as i32
+        }
+
+        fn "hour"(date_time: DateTime) {
+            date_time.fields().3 as i32
+        }
+
+        fn "minute"(date_time: DateTime) {
+            date_time.fields().4 as i32
+        }
+
+        fn "second"(date_time: DateTime) {
+            date_time.fields().5 as i32
+        }
+
+        fn "display"(date_time: DateTime) {
+            date_time.to_string()
+        }
+
+        fn "display"(duration: Duration) {
+            format!("{:?}", duration)
+        }
+
+        fn "+"(instant: Instant, duration: Duration) {
+            instant.checked_add(duration).ok_or_else(overflow)
+        }
+
+        fn "-"(instant: Instant, duration: Duration) {
+            instant.checked_sub(duration).ok_or_else(overflow)
+        }
+
+        // saturates at zero, like `Instant::duration_since`
+        fn "-"(a: Instant, b: Instant) {
+            a.saturating_duration_since(b)
+        }
+
+        fn "+"(a: Duration, b: Duration) {
+            a.checked_add(b).ok_or_else(overflow)
+        }
+
+        fn "-"(a: Duration, b: Duration) {
+            a.checked_sub(b).ok_or_else(|| time_error("negative duration"))
+        }
+
+        fn "*"(duration: Duration, n: i32) {
+            duration.checked_mul(non_negative(n)? as u32).ok_or_else(overflow)
+        }
+
+        fn "+"(date_time: DateTime, duration: Duration) {
+            date_time.checked_add(duration).ok_or_else(overflow)
+        }
+
+        fn "-"(date_time: DateTime, duration: Duration) {
+            date_time.checked_sub(duration).ok_or_else(overflow)
+        }
+
+        fn "-"(a: DateTime, b: DateTime) {
+            a.since(&b).ok_or_else(|| time_error("negative duration"))
+        }
+
+        fn "=="(a: Duration, b: Duration) {
+            a == b
+        }
+
+        fn "!="(a: Duration, b: Duration) {
+            a != b
+        }
+
+        fn "<"(a: Duration, b: Duration) {
+            a < b
+        }
+
+        fn ">"(a: Duration, b: Duration) {
+            a > b
+        }
+
+        fn "<="(a: Duration, b: Duration) {
+            a <= b
+        }
+
+        fn ">="(a: Duration, b: Duration) {
+            a >= b
+        }
+
+        fn "=="(a: DateTime, b: DateTime) {
+            a == b
+        }
+
+        fn "!="(a: DateTime, b: DateTime) {
+            a != b
+        }
+
+        fn "<"(a: DateTime, b: DateTime) {
+            a < b
+        }
+
+        fn ">"(a: DateTime, b: DateTime) {
+            a > b
+        }
+
+        fn "<="(a: DateTime, b: DateTime) {
+            a <= b
+        }
+
+        fn ">="(a: DateTime, b: DateTime) {
+            a >= b
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn iso_8601() {
+        let date_time = DateTime::parse("2024-02-29T23:30:00.25+02:00").unwrap();
+
+        assert_eq!(date_time.to_string(), "2024-02-29T23:30:00.25+02:00");
+        assert_eq!(date_time.secs, 1_709_242_200);
+        assert_eq!(
+            DateTime::parse("1969-12-31").unwrap().to_string(),
+            "1969-12-31T00:00:00Z"
+        );
+
+        assert!(DateTime::parse("2023-02-29").is_err());
+        assert!(DateTime::parse("2024-01-01T25:00:00Z").is_err());
+        assert_eq!(
+            DateTime::parse("2024-01-01T12:00:00").unwrap(),
+            DateTime::parse("2024-01-01T13:00:00+01:00").unwrap()
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(
+            eval(r#"(std::time::parse("2024-12-31T23:59:30Z") + std::time::secs(45)).to_string()"#),
+            "2025-01-01T00:00:15Z"
+        );
+        assert_eq!(
+            eval(r#"(std::time::parse("2024-01-02") - std::time::parse("2024-01-01")).as_secs()"#),
+            "86400"
+        );
+        assert_eq!(
+            eval("(std::time::millis(1500) * 2 - std::time::secs(1)).as_millis()"),
+            "2000"
+        );
+        assert_eq!(
+            eval("std::time::from_timestamp(0).with_offset(-90).hour()"),
+            "22"
+        );
+        assert_eq!(eval("std::time::secs(1) < std::time::millis(1001)"), "true");
+        assert_eq!(
+            eval("let start = std::time::now(); (std::time::now() - start).as_secs()"),
+            "0"
+        );
+
+        assert!(try_eval("std::time::secs(1) - std::time::secs(2)").is_err());
+        assert!(try_eval("std::time::secs(100000000000000000000.0)").is_err());
+    }
+}
@@ -0,0 +1,524 @@
+use crate::error::*;
+use crate::shared::*;
+use crate::variant::*;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// Deepest nesting [`Json::parse`] accepts before giving up.
+const MAX_DEPTH: usize = 128;
+
+/// A JSON document, shaped like `serde_json::Value` so trees from other JSON libraries
+/// map onto it one to one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+fn json_error(message: impl std::fmt::Display) -> Error {
+    Error::from_raw(ErrorKind::InvalidArgument, format!("json: {}", message))
+}
+
+impl Json {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut parser = Parser {
+            source: source.as_bytes(),
+            position: 0,
+            depth: 0,
+        };
+
+        let json = parser.value()?;
+        parser.whitespace();
+
+        if parser.position < source.len() {
+            return Err(parser.error("trailing characters"));
+        }
+
+        Ok(json)
+    }
+
+    /// Converts `union` into JSON, failing on values JSON can't represent and on
+    /// references that lead back to themselves.
+    pub fn from_union(union: &Union) -> Result<Self, Error> {
+        from_union(union, &mut Vec::new())
+    }
+
+    /// Converts into script values, objects become [`Map`]s and numbers too big for
+    /// an `Int` become `Float`s.
+    pub fn into_union(self) -> Union {
+        match self {
+            Self::Null => Union::Unit(()),
+            Self::Bool(b) => Union::Bool(b),
+            Self::Int(i) => match i32::try_from(i) {
+                Ok(i) => Union::Int(i),
+                Err(_) => Union::Float(i as f32),
+            },
+            Self::Float(f) => Union::Float(f as f32),
+            Self::String(s) => Union::from(s),
+            Self::Array(items) => Union::from(
+                items
+                    .into_iter()
+                    .map(|item| UnionCell::from(item.into_union()))
+                    .collect::<Vec<_>>(),
+            ),
+            Self::Object(entries) => Union::from(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, UnionCell::from(value.into_union())))
+                    .collect::<Map>(),
+            ),
+        }
+    }
+
+    /// Serializes to a string, indented by two spaces per level if `pretty`.
+    pub fn to_string(&self, pretty: bool) -> String {
+        let mut out = String::new();
+        self.write(&mut out, pretty, 0);
+
+        out
+    }
+
+    fn write(&self, out: &mut String, pretty: bool, indent: usize) {
+        let newline = |out: &mut String, indent: usize| {
+            if pretty {
+                out.push('\n');
+                out.extend(std::iter::repeat_n(' ', indent * 2));
+            }
+        };
+
+        match self {
+            Self::Null => out.push_str("null"),
+            Self::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Self::Int(i) => out.push_str(&i.to_string()),
+            // debug formatting always keeps a `.0` or exponent, so floats stay floats
+            Self::Float(f) => out.push_str(&format!("{:?}", f)),
+            Self::String(s) => write_string(out, s),
+            Self::Array(items) if items.is_empty() => out.push_str("[]"),
+            Self::Array(items) => {
+                out.push('[');
+
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+
+                    newline(out, indent + 1);
+                    item.write(out, pretty, indent + 1);
+                }
+
+                newline(out, indent);
+                out.push(']');
+            }
+            Self::Object(entries) if entries.is_empty() => out.push_str("{}"),
+            Self::Object(entries) => {
+                out.push('{');
+
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+
+                    newline(out, indent + 1);
+                    write_string(out, key);
+                    out.push_str(if pretty { ": " } else { ":" });
+                    value.write(out, pretty, indent + 1);
+                }
+
+                newline(out, indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+/// `visiting` holds the shared values currently being converted, meeting one of them
+/// again means a cycle.
+fn from_union(union: &Union, visiting: &mut Vec<*const Lock<Union>>) -> Result<Json, Error> {
+    match union {
+        Union::Int(i) => Ok(Json::Int(*i as i64)),
+        // going through the shortest representation keeps `0.1` from becoming
+        // `0.10000000149011612`
+        Union::Float(f) if f.is_finite() => Ok(Json::Float(f.to_string().parse().unwrap())),
+        Union::Float(f) => Err(json_error(format!("can't serialize {}", f))),
+        Union::Bool(b) => Ok(Json::Bool(*b)),
+        Union::String(s) => Ok(Json::String(s.to_string())),
+        Union::Unit(()) => Ok(Json::Null),
+        Union::Reference(variable) => cell_from_union(&variable.union, visiting),
+        Union::Variant(variant) => {
+            let any = Variant::as_any(&**variant);
+
+            if let Some(items) = any.downcast_ref::<Vec<UnionCell>>() {
+                items
+                    .iter()
+                    .map(|item| cell_from_union(item, visiting))
+                    .collect::<Result<_, _>>()
+                    .map(Json::Array)
+            } else if let Some(map) = any.downcast_ref::<Map>() {
+                map.iter()
+                    .map(|(key, value)| Ok((key.clone(), cell_from_union(value, visiting)?)))
+                    .collect::<Result<_, _>>()
+                    .map(Json::Object)
+            } else if let Some(option) = any.downcast_ref::<Option<Union>>() {
+                match option {
+                    Some(union) => from_union(union, visiting),
+                    None => Ok(Json::Null),
+                }
+            } else {
+                Err(json_error(format!("can't serialize {}", union)))
+            }
+        }
+        Union::Type(_) | Union::Range(_) | Union::Blob(_) => Err(json_error(format!(
+            "can't serialize {} of type {}",
+            union,
+            union.ty()
+        ))),
+    }
+}
+
+fn cell_from_union(
+    cell: &UnionCell,
+    visiting: &mut Vec<*const Lock<Union>>,
+) -> Result<Json, Error> {
+    match cell {
+        UnionCell::Owned(union) => from_union(union, visiting),
+        UnionCell::Shared(lock) => {
+            let ptr = Shared::as_ptr(lock);
+
+            if visiting.contains(&ptr) {
+                return Err(json_error("can't serialize a reference cycle"));
+            }
+
+            visiting.push(ptr);
+            let json = cell.map(|union| from_union(union, visiting));
+            visiting.pop();
+
+            json
+        }
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+struct Parser<'a> {
+    source: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> Error {
+        json_error(format!("{} at byte {}", message, self.position))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.source.get(self.position).copied()
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), Error> {
+        if self.source[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", literal)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, Error> {
+        self.whitespace();
+
+        match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'{') => self.nested(Self::object),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn nested(&mut self, f: fn(&mut Self) -> Result<Json, Error>) -> Result<Json, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+
+        self.depth += 1;
+        let json = f(self);
+        self.depth -= 1;
+
+        json
+    }
+
+    fn array(&mut self) -> Result<Json, Error> {
+        self.expect("[")?;
+
+        let mut items = Vec::new();
+
+        self.whitespace();
+
+        if self.peek() == Some(b']') {
+            self.position += 1;
+
+            return Ok(Json::Array(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+
+            self.whitespace();
+
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, Error> {
+        self.expect("{")?;
+
+        let mut entries = BTreeMap::new();
+
+        self.whitespace();
+
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+
+            return Ok(Json::Object(entries));
+        }
+
+        loop {
+            self.whitespace();
+
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key"));
+            }
+
+            let key = self.string()?;
+
+            self.whitespace();
+            self.expect(":")?;
+
+            entries.insert(key, self.value()?);
+
+            self.whitespace();
+
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+
+                    return Ok(Json::Object(entries));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, Error> {
+        let start = self.position;
+        let mut is_float = false;
+
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+
+        if self.peek() == Some(b'0')
+            && matches!(self.source.get(self.position + 1), Some(b'0'..=b'9'))
+        {
+            return Err(self.error("leading zero"));
+        }
+
+        let digits = |parser: &mut Self| {
+            let start = parser.position;
+
+            while let Some(b'0'..=b'9') = parser.peek() {
+                parser.position += 1;
+            }
+
+            if parser.position == start {
+                Err(parser.error("expected a digit"))
+            } else {
+                Ok(())
+            }
+        };
+
+        digits(self)?;
+
+        if self.peek() == Some(b'.') {
+            is_float = true;
+            self.position += 1;
+            digits(self)?;
+        }
+
+        if let Some(b'e' | b'E') = self.peek() {
+            is_float = true;
+            self.position += 1;
+
+            if let Some(b'+' | b'-') = self.peek() {
+                self.position += 1;
+            }
+
+            digits(self)?;
+        }
+
+        // only ascii was consumed, so this can't split a char
+        let number = std::str::from_utf8(&self.source[start..self.position]).unwrap();
+
+        match number.parse::<i64>() {
+            Ok(i) if !is_float => Ok(Json::Int(i)),
+            _ => number
+                .parse::<f64>()
+                .map(Json::Float)
+                .map_err(|_| self.error("invalid number")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.expect("\"")?;
+
+        let mut bytes = Vec::new();
+
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.position += 1;
+
+                    break;
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+
+                    let escaped = match self.peek() {
+                        Some(b'u') => {
+                            self.position += 1;
+                            self.unicode_escape()?
+                        }
+                        Some(b) => {
+                            let escaped = match b {
+                                b'"' => '"',
+                                b'\\' => '\\',
+                                b'/' => '/',
+                                b'b' => '\u{8}',
+                                b'f' => '\u{c}',
+                                b'n' => '\n',
+                                b'r' => '\r',
+                                b't' => '\t',
+                                _ => return Err(self.error("invalid escape")),
+                            };
+
+                            self.position += 1;
+                            escaped
+                        }
+                        None => return Err(self.error("unterminated string")),
+                    };
+
+                    bytes.extend(escaped.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(b) if b < 0x20 => return Err(self.error("control character in string")),
+                Some(b) => {
+                    self.position += 1;
+                    bytes.push(b);
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+
+        // the source is a `&str` and escapes produce whole chars
+        Ok(String::from_utf8(bytes).unwrap())
+    }
+
+    fn hex4(&mut self) -> Result<u32, Error> {
+        let hex = self
+            .source
+            .get(self.position..self.position + 4)
+            // `from_str_radix` would also take a sign
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+
+        self.position += 4;
+
+        Ok(hex)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, Error> {
+        let high = self.hex4()?;
+
+        let code = if (0xd800..0xdc00).contains(&high) {
+            // a surrogate pair encodes a char outside the basic plane
+            self.expect("\\u")?;
+            let low = self.hex4()?;
+
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("invalid surrogate pair"));
+            }
+
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let source = r#"{"a": [1, 2.5, -3e2], "b": {"c": null, "d": true}, "e": "x\"é😀"}"#;
+
+        let json = Json::parse(source).unwrap();
+
+        assert_eq!(
+            json.to_string(false),
+            r#"{"a":[1,2.5,-300.0],"b":{"c":null,"d":true},"e":"x\"é😀"}"#
+        );
+        assert_eq!(Json::parse(&json.to_string(true)).unwrap(), json);
+        assert_eq!(Json::from_union(&json.clone().into_union()).unwrap(), json);
+
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("01").is_err());
+        assert!(Json::parse(&"[".repeat(MAX_DEPTH + 1)).is_err());
+        assert!(Json::parse(r#""\u+041""#).is_err());
+    }
+}
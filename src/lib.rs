@@ -1,18 +1,36 @@
 pub mod ast;
+mod call_cache;
+pub mod cancellation;
+pub mod closure;
 pub mod control_flow;
 pub mod embedded_ctx_fn;
 pub mod embedded_fn;
+pub mod embedded_runtime_fn;
 pub mod engine;
 pub mod error;
 mod eval_expr;
 mod eval_stmt;
 pub mod fn_storage;
+pub mod format;
 pub mod function;
+pub mod json;
+pub mod limits;
 pub mod module;
+pub mod optimize;
+pub mod range;
+pub mod resolve;
+pub mod rng;
 pub mod runtime;
 pub mod scope;
+#[cfg(feature = "serde")]
+pub mod serialize;
+pub mod shared;
 pub mod span;
+pub mod symbol;
+#[cfg(test)]
+mod test_util;
 pub mod variant;
+pub mod vm;
 #[macro_use]
 pub mod macros;
 pub mod iron_std;
@@ -29,7 +47,15 @@ lalrpop_mod!(pub grammar);
 pub mod prelude {
     pub use crate::{def_module, module_items};
 
+    pub use crate::cancellation::*;
+    pub use crate::control_flow::*;
     pub use crate::engine::*;
+    pub use crate::error::*;
+    pub use crate::limits::*;
     pub use crate::runtime::*;
+    pub use crate::scope::*;
+    #[cfg(feature = "serde")]
+    pub use crate::serialize::{from_union, to_union};
     pub use crate::variant::*;
+    pub use crate::vm::Backend;
 }
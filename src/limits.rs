@@ -0,0 +1,318 @@
+use crate::cancellation::*;
+use crate::control_flow::*;
+use crate::error::*;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::variant::*;
+
+/// The call depth [`Limits::default`] allows.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1024;
+
+/// Stack left below which a call continues on a new segment, more than a call takes
+/// in a debug build.
+const STACK_RED_ZONE: usize = 1024 * 1024;
+/// Size of the segments deep recursion continues on.
+const STACK_SEGMENT: usize = 8 * 1024 * 1024;
+
+/// Caps on what a single run may use, [`None`] meaning unlimited. Every cap has its own
+/// [`ErrorKind`] so the host can tell a script that ran out of budget from one that
+/// failed on its own.
+///
+/// Only the call depth is capped by default, at [`DEFAULT_MAX_CALL_DEPTH`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Expressions and statements evaluated, see [`ErrorKind::TooManyOperations`].
+    pub max_operations: Option<u64>,
+    /// Nested script function and closure calls, see [`ErrorKind::CallDepthExceeded`].
+    ///
+    /// Calls grow the stack onto the heap when it runs low, so this bounds memory
+    /// rather than protecting the thread's stack, which is safe at any depth.
+    pub max_call_depth: Option<usize>,
+    /// Bytes in a string, see [`ErrorKind::StringTooLong`].
+    pub max_string_len: Option<usize>,
+    /// Items in an array or bytes in a blob, see [`ErrorKind::ArrayTooLong`].
+    pub max_array_len: Option<usize>,
+    /// Variables alive at once, see [`ErrorKind::TooManyVariables`].
+    pub max_variables: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_operations: None,
+            max_call_depth: Some(DEFAULT_MAX_CALL_DEPTH),
+            max_string_len: None,
+            max_array_len: None,
+            max_variables: None,
+        }
+    }
+}
+
+impl Limits {
+    #[inline(always)]
+    pub(crate) fn limits_sizes(&self) -> bool {
+        self.max_string_len.is_some() || self.max_array_len.is_some()
+    }
+
+    /// Checks a string of `len` bytes against its cap. Every function's result is
+    /// checked anyway, functions that can build huge strings call it before allocating.
+    pub fn check_string_len(&self, len: usize) -> Result<(), Error> {
+        match self.max_string_len {
+            Some(max) if len > max => Err(Error::from_raw(
+                ErrorKind::StringTooLong,
+                format!("string of length {} exceeds {}", len, max),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks an array or blob of `len` items against its cap, like
+    /// [`Limits::check_string_len`].
+    pub fn check_array_len(&self, len: usize) -> Result<(), Error> {
+        match self.max_array_len {
+            Some(max) if len > max => Err(Error::from_raw(
+                ErrorKind::ArrayTooLong,
+                format!("array of length {} exceeds {}", len, max),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Runs the body of a script function or closure, on a new stack segment if the
+/// current one is nearly used up, so recursion ends at the call depth cap instead of
+/// overflowing the thread's stack.
+#[inline(always)]
+pub(crate) fn grow_stack<R>(body: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, body)
+}
+
+fn exceeded(kind: ErrorKind, message: String) -> ControlFlow {
+    Error::from_raw(kind, message).into()
+}
+
+impl<'a, T> Runtime<'a, T> {
+    #[inline(always)]
+    pub(crate) fn count_operation(&mut self) -> Result<(), ControlFlow> {
+        self.operations += 1;
+
+        if self.operations.is_multiple_of(PROGRESS_INTERVAL) {
+            self.report_progress()?;
+        }
+
+        match self.limits.max_operations {
+            Some(max) if self.operations > max => Err(exceeded(
+                ErrorKind::TooManyOperations,
+                format!("exceeded {} operations", max),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts `n` operations at once, the same as `n` calls of
+    /// [`Runtime::count_operation`].
+    #[inline(always)]
+    pub(crate) fn count_operations(&mut self, n: u64) -> Result<(), ControlFlow> {
+        let counted = self.operations + n;
+
+        let reports = counted / PROGRESS_INTERVAL != self.operations / PROGRESS_INTERVAL;
+        let exceeds = matches!(self.limits.max_operations, Some(max) if counted > max);
+
+        if reports || exceeds {
+            for _ in 0..n {
+                self.count_operation()?;
+            }
+        } else {
+            self.operations = counted;
+        }
+
+        Ok(())
+    }
+
+    /// Call before running a script function or closure, pair every successful call
+    /// with [`Runtime::exit_call`].
+    #[inline(always)]
+    pub(crate) fn enter_call(&mut self) -> Result<(), ControlFlow> {
+        self.check_cancelled()?;
+
+        match self.limits.max_call_depth {
+            Some(max) if self.call_depth >= max => Err(exceeded(
+                ErrorKind::CallDepthExceeded,
+                format!("exceeded a call depth of {}", max),
+            )),
+            _ => {
+                self.call_depth += 1;
+
+                Ok(())
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn exit_call(&mut self) {
+        self.call_depth -= 1;
+    }
+
+    /// Checks strings, arrays and blobs against their caps, looking through references.
+    pub(crate) fn check_size(&self, cell: &UnionCell) -> Result<(), ControlFlow> {
+        cell.map(|union| match union {
+            Union::String(s) => Ok(self.limits.check_string_len(s.len())?),
+            Union::Blob(b) => Ok(self.limits.check_array_len(b.len())?),
+            Union::Reference(variable) => self.check_size(&variable.union),
+            union => match union.downcast_ref::<Vec<UnionCell>>() {
+                Some(items) => Ok(self.limits.check_array_len(items.len())?),
+                None => Ok(()),
+            },
+        })
+    }
+
+    #[inline(always)]
+    pub(crate) fn check_variables(&self, scope: &Scope<T>) -> Result<(), ControlFlow> {
+        match self.limits.max_variables {
+            Some(max) if scope.len() > max => Err(exceeded(
+                ErrorKind::TooManyVariables,
+                format!("exceeded {} variables", max),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::*;
+    use crate::vm::*;
+
+    fn run(limits: Limits, source: &str) -> ErrorKind {
+        let mut engine = Engine::<()>::new();
+        engine.set_limits(limits);
+
+        engine.eval(&mut (), source).unwrap_err().kind
+    }
+
+    #[test]
+    fn limits() {
+        let limits = Limits::default();
+
+        assert!(matches!(
+            run(
+                Limits {
+                    max_operations: Some(1000),
+                    ..limits
+                },
+                "while true {}"
+            ),
+            ErrorKind::TooManyOperations
+        ));
+        assert!(matches!(
+            run(
+                Limits {
+                    max_call_depth: Some(4),
+                    ..limits
+                },
+                "fn f(x) { f(x + 1) } f(0)"
+            ),
+            ErrorKind::CallDepthExceeded
+        ));
+        assert!(matches!(
+            run(
+                Limits {
+                    max_string_len: Some(16),
+                    ..limits
+                },
+                "let s = \"ab\"; while true { s = s + s; }"
+            ),
+            ErrorKind::StringTooLong
+        ));
+        assert!(matches!(
+            run(
+                Limits {
+                    max_array_len: Some(16),
+                    ..limits
+                },
+                "let a = []; while true { a.push(1); }"
+            ),
+            ErrorKind::ArrayTooLong
+        ));
+        assert!(matches!(
+            run(
+                Limits {
+                    max_variables: Some(8),
+                    ..limits
+                },
+                "fn f(x) { let y = x; f(y) } f(0)"
+            ),
+            ErrorKind::TooManyVariables
+        ));
+
+        // these would allocate hundreds of megabytes if sizes were only checked afterwards
+        let sizes = Limits {
+            max_string_len: Some(100),
+            max_array_len: Some(100),
+            ..limits
+        };
+
+        for source in [
+            "\"ab\".repeat(400000000)",
+            "format(\"{:>400000000}\", 1)",
+            "let a = [\"ab\".repeat(50)]; a.extend(a); a.extend(a); a.join(\"\")",
+        ] {
+            assert!(matches!(run(sizes, source), ErrorKind::StringTooLong));
+        }
+
+        for source in [
+            "blob(400000000)",
+            "let a = []; for i in 0..60 { a.push(i); } a.concat(a)",
+            "let a = []; for i in 0..60 { a.push(i); } a.extend(a)",
+        ] {
+            assert!(matches!(run(sizes, source), ErrorKind::ArrayTooLong));
+        }
+
+        let mut engine = Engine::<()>::new();
+        engine.set_limits(Limits {
+            max_operations: Some(1000),
+            max_call_depth: Some(8),
+            ..limits
+        });
+
+        // the budget is per run
+        for _ in 0..3 {
+            assert!(engine.eval(&mut (), "fn f(x) { x + 1 } f(f(1))").is_ok());
+        }
+    }
+
+    #[test]
+    fn deep_recursion() {
+        let recurse = |n: usize| {
+            format!(
+                "fn r(n) {{ if n == 0 {{ 0 }} else {{ r(n - 1) }} }} r({})",
+                n
+            )
+        };
+
+        for backend in [Backend::TreeWalker, Backend::Vm] {
+            let mut engine = Engine::<()>::new();
+            engine.set_backend(backend);
+
+            // `r(n)` makes `n + 1` calls, on a test thread's small stack
+            let deepest = recurse(DEFAULT_MAX_CALL_DEPTH - 1);
+            assert_eq!(engine.eval(&mut (), &deepest).unwrap().to_string(), "0");
+
+            for n in [DEFAULT_MAX_CALL_DEPTH, 1000000] {
+                let source = recurse(n);
+
+                assert!(matches!(
+                    engine.eval(&mut (), &source).unwrap_err().kind,
+                    ErrorKind::CallDepthExceeded
+                ));
+            }
+
+            let closures = "let r = |r, n| if n == 0 { 0 } else { r(r, n - 1) }; r(r, 1000000)";
+            assert!(matches!(
+                engine.eval(&mut (), closures).unwrap_err().kind,
+                ErrorKind::CallDepthExceeded
+            ));
+        }
+    }
+}
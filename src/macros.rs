@@ -66,6 +66,14 @@ macro_rules! module_items {
         }
     };
 
+    (mod $ident:ident = $module:path; $($rest:tt)*) => {
+        |module: &mut $crate::module::Module<_>| {
+            module.register_sub_module(stringify!($ident), $module());
+
+            module_items!($($rest)*)(module);
+        }
+    };
+
     (fn $ident:literal ($($param_ident:tt : $param_ty:ty),*) $block:block $($rest:tt)*) => {
         |module: &mut $crate::module::Module<_>| {
             module.register_fn($ident, #[allow(unused_mut)]|$(mut $param_ident: $param_ty),*| $block).unwrap();
@@ -1,6 +1,8 @@
 use crate::error::*;
 use crate::fn_storage::*;
 use crate::function::*;
+use crate::symbol::*;
+use crate::variant::*;
 use std::collections::HashMap;
 
 #[derive(Debug)]
@@ -50,7 +52,7 @@ impl<T> Module<T> {
 
     pub fn register_fn<P, R, F, U>(
         &mut self,
-        ident: impl Into<String>,
+        ident: impl Into<Symbol>,
         f: F,
     ) -> Result<(), ErrorKind>
     where
@@ -77,16 +79,53 @@ impl<T> Module<T> {
         self.functions.register_fn(fn_signature, fn_type)
     }
 
+    /// Removes every overload of the function at `path`, eg. `std::sys::env`,
+    /// returns whether any existed.
+    pub fn remove_fn(&mut self, path: &str) -> bool {
+        match path.split_once("::") {
+            Some((sub_module, path)) => match self.sub_modules.get_mut(sub_module) {
+                Some(sub_module) => sub_module.remove_fn(path),
+                None => false,
+            },
+            None => self.functions.remove_fn(path),
+        }
+    }
+
+    /// The functions directly in this module, not in a sub module.
+    pub fn functions(&self) -> &FnStorage<T> {
+        &self.functions
+    }
+
+    /// Gets a function, idents of the form `a::b::f` are looked up in sub modules.
     #[inline(always)]
     pub fn get_fn(&self, fn_signature: &FnSignature) -> Result<&FnType<T>, ErrorKind> {
-        self.functions.get_fn(fn_signature)
+        let ident = fn_signature.ident;
+
+        if ident.contains("::") {
+            self.get_fn_path(&ident, &fn_signature.params)
+        } else {
+            self.functions.get_fn_raw(ident, &fn_signature.params)
+        }
+    }
+
+    #[inline(always)]
+    fn get_fn_path(&self, path: &str, params: &[UnionType]) -> Result<&FnType<T>, ErrorKind> {
+        match path.split_once("::") {
+            Some((sub_module, path)) => match self.sub_modules.get(sub_module) {
+                Some(sub_module) => sub_module.get_fn_path(path, params),
+                None => Err(ErrorKind::UndefinedFunction),
+            },
+            None => match Symbol::get(path) {
+                Some(ident) => self.functions.get_fn_raw(ident, params),
+                None => Err(ErrorKind::UndefinedFunction),
+            },
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::variant::*;
 
     #[test]
     fn register_fn() {
@@ -97,4 +136,24 @@ mod test {
         assert!(module.register_fn("test123", a).is_ok());
         assert!(module.register_fn("test123", a).is_err());
     }
+
+    #[test]
+    fn fn_path() {
+        fn a(_: i32) {}
+
+        let mut sub_module = Module::<()>::new();
+        sub_module.register_fn("a", a).unwrap();
+
+        let mut module = Module::<()>::new();
+        module.register_sub_module("sub", sub_module);
+
+        let fn_signature = FnSignature {
+            ident: "sub::a".into(),
+            params: vec![UnionType::Int],
+        };
+
+        assert!(module.get_fn(&fn_signature).is_ok());
+        assert!(module.remove_fn("sub::a"));
+        assert!(module.get_fn(&fn_signature).is_err());
+    }
 }
@@ -0,0 +1,531 @@
+use crate::ast::*;
+use crate::fn_storage::*;
+use crate::module::*;
+use crate::span::*;
+use crate::symbol::*;
+use crate::variant::*;
+use fnv::FnvHashSet;
+use std::sync::Arc;
+
+/// How much an [`Engine`](crate::engine::Engine) rewrites a program before running it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptimizationLevel {
+    /// Runs the program as written.
+    None,
+    /// Folds operators on constants and drops the branches of `if`s on constants.
+    #[default]
+    Simple,
+    /// Also removes `let`s of values without side effects that are never used.
+    Full,
+}
+
+/// Rewrites `program` to do less at runtime without changing what it does, has to run
+/// before [`resolve`](crate::resolve::resolve).
+///
+/// An operator is only folded when neither the functions in `module` nor the program
+/// itself overload it for the types of its operands.
+pub fn optimize<T>(program: &mut Block, module: &Module<T>, level: OptimizationLevel) {
+    if level == OptimizationLevel::None {
+        return;
+    }
+
+    let optimizer = Optimizer {
+        module,
+        level,
+        defined: defined_fns(program),
+    };
+
+    optimizer.block(program);
+}
+
+/// Names of the functions `program` defines anywhere, which may overload operators at
+/// runtime.
+pub(crate) fn defined_fns(program: &Block) -> FnvHashSet<Symbol> {
+    let mut defined = FnvHashSet::default();
+
+    walk_block(program, &mut |node| {
+        if let Node::Stmt(Stmt::FnDef { fn_signature, .. }) = node {
+            defined.insert(fn_signature.ident);
+        }
+    });
+
+    defined
+}
+
+struct Optimizer<'a, T> {
+    module: &'a Module<T>,
+    level: OptimizationLevel,
+    /// Functions defined by the program, which may overload operators at runtime.
+    defined: FnvHashSet<Symbol>,
+}
+
+impl<'a, T> Optimizer<'a, T> {
+    fn block(&self, block: &mut Block) {
+        self.open_block(block);
+
+        if self.level >= OptimizationLevel::Full {
+            remove_unused_lets(block);
+        }
+    }
+
+    /// Optimizes an `if` or `try` block, whose `let`s outlive it so none are unused.
+    fn open_block(&self, block: &mut Block) {
+        for stmt in &mut block.stmts {
+            self.stmt(stmt);
+        }
+
+        if let Some(expr) = &mut block.expr {
+            self.expr(expr);
+        }
+    }
+
+    fn stmt(&self, stmt: &mut Spanned<Stmt>) {
+        match &mut **stmt {
+            Stmt::Let { expr, .. } | Stmt::Expr { expr } => self.expr(expr),
+            Stmt::FnDef { block, .. } => self.block(&mut Arc::make_mut(block).inner),
+        }
+    }
+
+    fn expr(&self, expr: &mut Spanned<Expr>) {
+        match &mut **expr {
+            Expr::Literal { .. } | Expr::Constant { .. } | Expr::Variable { .. } => {}
+
+            Expr::Array { items } => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+
+            Expr::Closure { body, .. } => self.expr(Arc::make_mut(body)),
+
+            Expr::Assign { target, variable } => {
+                self.expr(target);
+                self.expr(variable);
+            }
+
+            Expr::NegationOp { expr: operand } => {
+                self.expr(operand);
+
+                if let Some(Union::Bool(b)) = constant(operand) {
+                    if !self.overloaded("!".into(), &[UnionType::Bool]) {
+                        let variant = Union::Bool(!b);
+
+                        **expr = Expr::Constant { variant };
+                    }
+                }
+            }
+
+            Expr::BinOp { lhs, rhs, op, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+
+                if let Some(variant) = self.fold(op.inner, lhs, rhs) {
+                    **expr = Expr::Constant { variant };
+                }
+            }
+
+            Expr::Reference { expr } | Expr::Dereference { expr } | Expr::Try { expr } => {
+                self.expr(expr)
+            }
+
+            Expr::Block { block } => self.block(block),
+
+            Expr::If {
+                check,
+                block,
+                else_block,
+            } => {
+                self.expr(check);
+                self.open_block(block);
+
+                if let Some(else_block) = else_block {
+                    self.expr(else_block);
+                }
+
+                // a block opens a sub scope but an `if` body declares into the enclosing one,
+                // so a taken branch with lets stays an `if`
+                let declares = block
+                    .stmts
+                    .iter()
+                    .any(|stmt| matches!(stmt.inner, Stmt::Let { .. }));
+
+                match constant(check) {
+                    Some(Union::Bool(true)) if !declares => {
+                        let block = std::mem::replace(&mut block.inner, empty_block());
+
+                        **expr = Expr::Block {
+                            block: Box::new(block),
+                        };
+                    }
+                    Some(Union::Bool(false)) => match else_block.take() {
+                        Some(else_block) => *expr = *else_block,
+                        None => {
+                            **expr = Expr::Literal {
+                                variant: Union::Unit(()),
+                            }
+                        }
+                    },
+                    _ => {}
+                }
+            }
+
+            Expr::FnCall { params, .. } => {
+                for param in params {
+                    self.expr(param);
+                }
+            }
+
+            Expr::MethodCall { caller, params, .. } => {
+                self.expr(caller);
+
+                for param in params {
+                    self.expr(param);
+                }
+            }
+
+            Expr::TryCatch {
+                try_block,
+                catch_block,
+            } => {
+                self.open_block(try_block);
+                self.open_block(catch_block);
+            }
+
+            Expr::WhileLoop { expr, block } | Expr::ForLoop { expr, block, .. } => {
+                self.expr(expr);
+                self.block(block);
+            }
+
+            Expr::Range { start, end, .. } => {
+                for bound in start.iter_mut().chain(end.iter_mut()) {
+                    self.expr(bound);
+                }
+            }
+
+            Expr::Match { expr, arms } => {
+                self.expr(expr);
+
+                for arm in arms {
+                    self.expr(&mut arm.expr);
+                }
+            }
+        }
+    }
+
+    fn overloaded(&self, ident: Symbol, params: &[UnionType]) -> bool {
+        let fn_signature = FnSignature {
+            ident,
+            params: params.to_vec(),
+        };
+
+        self.defined.contains(&ident) || self.module.get_fn(&fn_signature).is_ok()
+    }
+
+    /// Applies the built in operator `op` to constant operands, the same way the
+    /// runtime would.
+    fn fold(&self, op: Symbol, lhs: &Spanned<Expr>, rhs: &Spanned<Expr>) -> Option<Union> {
+        let lhs = constant(lhs)?;
+        let rhs = constant(rhs)?;
+
+        if self.overloaded(op, &[lhs.ty(), rhs.ty()]) || panics(lhs, rhs, &op) {
+            return None;
+        }
+
+        crate::internal_binop::internal_binop(lhs.clone(), rhs.clone(), &op)
+    }
+}
+
+fn constant(expr: &Spanned<Expr>) -> Option<&Union> {
+    match &**expr {
+        Expr::Literal { variant } | Expr::Constant { variant } => Some(variant),
+        _ => None,
+    }
+}
+
+/// Whether the built in integer operators would overflow or divide by zero, which is
+/// left for the runtime to run into.
+fn panics(lhs: &Union, rhs: &Union, op: &str) -> bool {
+    let (lhs, rhs) = match (lhs, rhs) {
+        (Union::Int(lhs), Union::Int(rhs)) => (*lhs, *rhs),
+        _ => return false,
+    };
+
+    let result = match op {
+        "+" => lhs.checked_add(rhs),
+        "-" => lhs.checked_sub(rhs),
+        "*" => lhs.checked_mul(rhs),
+        "/" => lhs.checked_div(rhs),
+        "%" => lhs.checked_rem(rhs),
+        _ => Some(0),
+    };
+
+    result.is_none()
+}
+
+fn empty_block() -> Block {
+    Block {
+        stmts: Vec::new(),
+        expr: None,
+    }
+}
+
+/// Whether evaluating `expr` can't fail or have an effect.
+fn pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal { .. } | Expr::Constant { .. } | Expr::Closure { .. } => true,
+        Expr::Array { items } => items.iter().all(|item| pure(item)),
+        _ => false,
+    }
+}
+
+/// Removes the `let`s of pure values that nothing after them in `block` mentions,
+/// including the named arguments of format strings.
+fn remove_unused_lets(block: &mut Block) {
+    let mut mentions = Mentions::default();
+
+    if let Some(expr) = &block.expr {
+        walk_expr(expr, &mut |node| mentions.add(node));
+    }
+
+    let mut stmts = Vec::with_capacity(block.stmts.len());
+
+    for stmt in block.stmts.drain(..).rev() {
+        if let Stmt::Let {
+            ident,
+            ty: None,
+            expr,
+        } = &*stmt
+        {
+            // `mentions` holds everything after this `let`
+            if pure(expr) && !mentions.contains(*ident) {
+                continue;
+            }
+        }
+
+        walk_stmt(&stmt, &mut |node| mentions.add(node));
+        stmts.push(stmt);
+    }
+
+    stmts.reverse();
+    block.stmts = stmts;
+}
+
+/// The variables a part of a program might use.
+#[derive(Default)]
+struct Mentions {
+    idents: FnvHashSet<Symbol>,
+    /// String literals, which name variables when used as format strings.
+    strings: Vec<SharedString>,
+}
+
+impl Mentions {
+    fn add(&mut self, node: Node<'_>) {
+        match node {
+            Node::Expr(Expr::Variable { ident, .. }) | Node::Expr(Expr::FnCall { ident, .. }) => {
+                self.idents.insert(ident.inner);
+            }
+            Node::Expr(Expr::Literal {
+                variant: Union::String(string),
+            }) => self.strings.push(string.clone()),
+            _ => {}
+        }
+    }
+
+    fn contains(&self, ident: Symbol) -> bool {
+        self.idents.contains(&ident)
+            || self
+                .strings
+                .iter()
+                .any(|string| string.contains(ident.as_str()))
+    }
+}
+
+enum Node<'a> {
+    Stmt(&'a Stmt),
+    Expr(&'a Expr),
+}
+
+/// Calls `f` with every statement and expression in `block`, nested ones included.
+fn walk_block(block: &Block, f: &mut impl FnMut(Node<'_>)) {
+    for stmt in &block.stmts {
+        walk_stmt(stmt, f);
+    }
+
+    if let Some(expr) = &block.expr {
+        walk_expr(expr, f);
+    }
+}
+
+fn walk_stmt(stmt: &Stmt, f: &mut impl FnMut(Node<'_>)) {
+    f(Node::Stmt(stmt));
+
+    match stmt {
+        Stmt::Let { expr, .. } | Stmt::Expr { expr } => walk_expr(expr, f),
+        Stmt::FnDef { block, .. } => walk_block(block, f),
+    }
+}
+
+fn walk_expr(expr: &Expr, f: &mut impl FnMut(Node<'_>)) {
+    f(Node::Expr(expr));
+
+    match expr {
+        Expr::Literal { .. } | Expr::Constant { .. } | Expr::Variable { .. } => {}
+
+        Expr::Closure { body, .. } => walk_expr(body, f),
+
+        Expr::Assign {
+            target: lhs,
+            variable: rhs,
+        }
+        | Expr::BinOp { lhs, rhs, .. } => {
+            walk_expr(lhs, f);
+            walk_expr(rhs, f);
+        }
+
+        Expr::NegationOp { expr }
+        | Expr::Reference { expr }
+        | Expr::Dereference { expr }
+        | Expr::Try { expr } => walk_expr(expr, f),
+
+        Expr::Block { block } => walk_block(block, f),
+
+        Expr::If {
+            check,
+            block,
+            else_block,
+        } => {
+            walk_expr(check, f);
+            walk_block(block, f);
+
+            if let Some(else_block) = else_block {
+                walk_expr(else_block, f);
+            }
+        }
+
+        Expr::Array { items: params } | Expr::FnCall { params, .. } => {
+            for param in params {
+                walk_expr(param, f);
+            }
+        }
+
+        Expr::MethodCall { caller, params, .. } => {
+            walk_expr(caller, f);
+
+            for param in params {
+                walk_expr(param, f);
+            }
+        }
+
+        Expr::TryCatch {
+            try_block,
+            catch_block,
+        } => {
+            walk_block(try_block, f);
+            walk_block(catch_block, f);
+        }
+
+        Expr::WhileLoop { expr, block } | Expr::ForLoop { expr, block, .. } => {
+            walk_expr(expr, f);
+            walk_block(block, f);
+        }
+
+        Expr::Range { start, end, .. } => {
+            for bound in start.iter().chain(end.iter()) {
+                walk_expr(bound, f);
+            }
+        }
+
+        Expr::Match { expr, arms } => {
+            walk_expr(expr, f);
+
+            for arm in arms {
+                walk_expr(&arm.expr, f);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::*;
+
+    fn optimized(source: &str, level: OptimizationLevel) -> Block {
+        let mut program = crate::grammar::BlockParser::new().parse(source).unwrap();
+        optimize(&mut program, &Module::<()>::new(), level);
+
+        program
+    }
+
+    fn constant_value(expr: &Option<Spanned<Expr>>) -> Option<String> {
+        match expr.as_deref() {
+            Some(Expr::Constant { variant }) => Some(variant.to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn folds_constants() {
+        let program = optimized("2 * 60 * 60", OptimizationLevel::Simple);
+        assert_eq!(constant_value(&program.expr).as_deref(), Some("7200"));
+
+        let program = optimized("1 / 0", OptimizationLevel::Simple);
+        assert!(matches!(program.expr.as_deref(), Some(Expr::BinOp { .. })));
+
+        let program = optimized("if !(1 < 2) { 1 } else { 2 }", OptimizationLevel::Simple);
+        assert!(matches!(program.expr.as_deref(), Some(Expr::Block { .. })));
+
+        let program = optimized("let a = 1; 2 + 3", OptimizationLevel::None);
+        assert!(matches!(program.expr.as_deref(), Some(Expr::BinOp { .. })));
+        assert_eq!(program.stmts.len(), 1);
+    }
+
+    #[test]
+    fn removes_unused_lets() {
+        let program = optimized(
+            "let a = [1, 2]; let b = 3; let c = d(); let e = 4; format(\"{e}\") + b",
+            OptimizationLevel::Full,
+        );
+
+        let idents: Vec<_> = program
+            .stmts
+            .iter()
+            .filter_map(|stmt| match &**stmt {
+                Stmt::Let { ident, .. } => Some(ident.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(idents, ["b", "c", "e"]);
+    }
+
+    #[test]
+    fn same_results() {
+        let sources = [
+            "let t = 2 * 60 * 60; if t > 3600 { t / 60 } else { 0 }",
+            "fn +(a: i32, b: i32) { a * b } 2 + 3",
+            "let x = 1 + 2; x = \"a\"; x",
+            "let hp = 7; let unused = || 1; if false { 1 } else if true { format(\"{hp}\") } else { \"\" }",
+            "let a = 1; if 2 > 1 { let a = 2; } a",
+        ];
+
+        for source in &sources {
+            let results: Vec<_> = [
+                OptimizationLevel::None,
+                OptimizationLevel::Simple,
+                OptimizationLevel::Full,
+            ]
+            .iter()
+            .map(|level| {
+                let mut engine = Engine::<()>::new();
+                engine.set_optimization_level(*level);
+
+                format!("{:?}", engine.eval(&mut (), *source).map(|u| u.to_string()))
+            })
+            .collect();
+
+            assert_eq!(results[0], results[1], "{}", source);
+            assert_eq!(results[0], results[2], "{}", source);
+        }
+    }
+}
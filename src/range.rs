@@ -0,0 +1,163 @@
+use crate::error::*;
+
+/// A range of integers, `start..end`, `start..=end` or any of them with open ends.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Range {
+    pub start: Option<i32>,
+    pub end: Option<i32>,
+    pub inclusive: bool,
+}
+
+impl std::fmt::Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(start) = self.start {
+            write!(f, "{}", start)?;
+        }
+
+        write!(f, "{}", if self.inclusive { "..=" } else { ".." })?;
+
+        if let Some(end) = self.end {
+            write!(f, "{}", end)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Range {
+    pub fn new(start: i32, end: i32) -> Self {
+        Self {
+            start: Some(start),
+            end: Some(end),
+            inclusive: false,
+        }
+    }
+
+    /// The end of the range as an exclusive bound, [`None`] if it's open.
+    #[inline(always)]
+    pub fn exclusive_end(&self) -> Option<i64> {
+        self.end.map(|end| {
+            if self.inclusive {
+                end as i64 + 1
+            } else {
+                end as i64
+            }
+        })
+    }
+
+    #[inline(always)]
+    pub fn contains(&self, value: i32) -> bool {
+        let value = value as i64;
+
+        self.start.is_none_or(|start| start as i64 <= value)
+            && self.exclusive_end().is_none_or(|end| value < end)
+    }
+
+    /// Number of integers in the range, [`None`] if it's unbounded.
+    pub fn len(&self) -> Option<i64> {
+        match (self.start, self.exclusive_end()) {
+            (Some(start), Some(end)) => Some((end - start as i64).max(0)),
+            _ => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    /// Resolves the range to indices into something of length `len`, open ends
+    /// extending to the start and end.
+    pub fn indices(&self, len: usize) -> Result<std::ops::Range<usize>, Error> {
+        let start = self.start.map_or(0, |start| start as i64);
+        let end = self.exclusive_end().unwrap_or(len as i64);
+
+        if start < 0 || end < start || end > len as i64 {
+            Err(Error::from_raw(
+                ErrorKind::IndexOutOfBounds,
+                format!("{} is out of bounds for length {}", self, len),
+            ))
+        } else {
+            Ok(start as usize..end as usize)
+        }
+    }
+
+    pub fn iter(&self) -> Result<RangeIter, Error> {
+        match self.start {
+            Some(start) => Ok(RangeIter {
+                next: start as i64,
+                end: self.exclusive_end(),
+                step: 1,
+            }),
+            None => Err(Error::from_raw(
+                ErrorKind::InvalidArgument,
+                format!("can't iterate {} without a start", self),
+            )),
+        }
+    }
+
+    pub fn rev(&self) -> Result<RangeIter, Error> {
+        match (self.start, self.exclusive_end()) {
+            (Some(start), Some(end)) => Ok(RangeIter {
+                next: end - 1,
+                end: Some(start as i64 - 1),
+                step: -1,
+            }),
+            _ => Err(Error::from_raw(
+                ErrorKind::InvalidArgument,
+                format!("can't reverse {} without both ends", self),
+            )),
+        }
+    }
+}
+
+/// Iterator over a [`Range`], counting towards `end` by `step`.
+#[derive(Clone, Debug)]
+pub struct RangeIter {
+    pub next: i64,
+    pub end: Option<i64>,
+    pub step: i64,
+}
+
+impl Iterator for RangeIter {
+    type Item = i32;
+
+    fn next(&mut self) -> Option<i32> {
+        let in_range = match self.end {
+            Some(end) if self.step > 0 => self.next < end,
+            Some(end) => self.next > end,
+            None => self.next <= i32::MAX as i64,
+        };
+
+        if in_range {
+            let next = self.next;
+            self.next += self.step;
+
+            Some(next as i32)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn range_iter() {
+        let range = Range::new(1, 4);
+
+        assert_eq!(range.iter().unwrap().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(range.rev().unwrap().collect::<Vec<_>>(), vec![3, 2, 1]);
+
+        let inclusive = Range {
+            inclusive: true,
+            ..range
+        };
+
+        assert_eq!(inclusive.iter().unwrap().count(), 4);
+        assert!(inclusive.contains(4));
+        assert!(!range.contains(4));
+        assert!(range.indices(3).is_err());
+    }
+}
@@ -0,0 +1,427 @@
+use crate::ast::*;
+use crate::format::*;
+use crate::scope::*;
+use crate::shared::Shared;
+use crate::span::*;
+use crate::symbol::*;
+use crate::variant::*;
+use std::sync::Arc;
+
+/// Assigns every variable in `program` its [`Slot`], so running it never looks a
+/// variable up by name, and numbers its [`CallSite`]s.
+///
+/// The sub scopes opened here have to match the ones opened at runtime exactly: the
+/// program, functions and closures get one with [`Scope::sub(true)`](Scope::sub),
+/// blocks, loop bodies and match arms one each.
+///
+/// `if` and `try`/`catch` blocks declare into the enclosing scope, so whether their
+/// variables exist depends on what ran. Those, and every variable after them in the
+/// same scope, get no slot and are found by name, see [`Scope::find`].
+pub fn resolve(program: &mut Block) {
+    let mut resolver = Resolver::default();
+
+    resolver.function(Names::default(), |resolver| resolver.block(program));
+}
+
+#[derive(Default)]
+struct Names {
+    idents: Vec<Symbol>,
+    /// Index of the first variable that may or may not have been declared, from there
+    /// on indices aren't known.
+    unsure_from: Option<usize>,
+}
+
+impl Names {
+    fn new(idents: Vec<Symbol>) -> Self {
+        Self {
+            idents,
+            unsure_from: None,
+        }
+    }
+}
+
+/// Where a variable is found.
+enum Found {
+    Slot(Slot),
+    ByName,
+    Nowhere,
+}
+
+#[derive(Default)]
+struct Resolver {
+    /// Names pushed into each open sub scope of the current function, innermost last.
+    scopes: Vec<Names>,
+    sites: CallSite,
+}
+
+impl Resolver {
+    /// Resolves a function body, which only sees its own variables, starting with
+    /// `idents`.
+    fn function(&mut self, names: Names, f: impl FnOnce(&mut Self)) {
+        let outer = std::mem::replace(&mut self.scopes, vec![names]);
+
+        f(self);
+
+        self.scopes = outer;
+    }
+
+    fn sub(&mut self, idents: Vec<Symbol>, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(Names::new(idents));
+
+        f(self);
+
+        self.scopes.pop();
+    }
+
+    fn current(&mut self) -> &mut Names {
+        self.scopes.last_mut().expect("no scope to declare in")
+    }
+
+    /// Resolves what may or may not run, like an `if` block, anything it declares
+    /// in the current scope loses its slot.
+    fn maybe(&mut self, f: impl FnOnce(&mut Self)) {
+        let start = self.current().idents.len();
+
+        f(self);
+
+        let names = self.current();
+
+        if names.idents.len() > start && names.unsure_from.is_none() {
+            names.unsure_from = Some(start);
+        }
+    }
+
+    fn declare(&mut self, ident: Symbol) {
+        self.current().idents.push(ident);
+    }
+
+    /// Finds the latest variable called `ident`, like shadowing does.
+    fn find(&self, ident: Symbol) -> Found {
+        for (depth, names) in self.scopes.iter().rev().enumerate() {
+            if let Some(index) = names.idents.iter().rposition(|name| *name == ident) {
+                return match names.unsure_from {
+                    Some(unsure_from) if index >= unsure_from => Found::ByName,
+                    _ => Found::Slot(Slot { depth, index }),
+                };
+            }
+        }
+
+        Found::Nowhere
+    }
+
+    fn lookup(&self, ident: Symbol) -> Option<Slot> {
+        match self.find(ident) {
+            Found::Slot(slot) => Some(slot),
+            Found::ByName | Found::Nowhere => None,
+        }
+    }
+
+    fn block(&mut self, block: &mut Block) {
+        for stmt in &mut block.stmts {
+            self.stmt(stmt);
+        }
+
+        if let Some(expr) = &mut block.expr {
+            self.expr(expr);
+        }
+    }
+
+    fn site(&mut self) -> CallSite {
+        self.sites += 1;
+        self.sites - 1
+    }
+
+    fn sub_block(&mut self, block: &mut Block) {
+        self.sub(Vec::new(), |resolver| resolver.block(block));
+    }
+
+    fn maybe_block(&mut self, block: &mut Block) {
+        self.maybe(|resolver| resolver.block(block));
+    }
+
+    fn stmt(&mut self, stmt: &mut Spanned<Stmt>) {
+        match &mut **stmt {
+            Stmt::Let { ident, expr, .. } => {
+                self.expr(expr);
+                self.declare(*ident);
+            }
+
+            Stmt::Expr { expr } => self.expr(expr),
+
+            Stmt::FnDef {
+                block,
+                parameter_idents,
+                ..
+            } => {
+                let block = Arc::make_mut(block);
+
+                self.function(Names::new(parameter_idents.to_vec()), |resolver| {
+                    resolver.block(block)
+                });
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Spanned<Expr>) {
+        match &mut **expr {
+            Expr::Literal { .. } | Expr::Constant { .. } => {}
+
+            Expr::Array { items } => {
+                for item in items {
+                    self.expr(item);
+                }
+            }
+
+            Expr::Closure {
+                parameter_idents,
+                body,
+            } => {
+                // captures everything visible, see `Scope::capture`, which is only
+                // known up to the first variable that may or may not exist
+                let mut names = Names::default();
+
+                for scope in &self.scopes {
+                    if let (None, Some(unsure_from)) = (names.unsure_from, scope.unsure_from) {
+                        names.unsure_from = Some(names.idents.len() + unsure_from);
+                    }
+
+                    names.idents.extend(&scope.idents);
+                }
+
+                names.idents.extend(parameter_idents.iter());
+                let body = Arc::make_mut(body);
+
+                self.function(names, |resolver| resolver.expr(body));
+            }
+
+            Expr::Variable { ident, slot } => *slot = self.lookup(ident.inner),
+
+            Expr::Assign { target, variable } => {
+                self.expr(target);
+                self.expr(variable);
+            }
+
+            Expr::BinOp { lhs, rhs, site, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+                *site = self.site();
+            }
+
+            Expr::NegationOp { expr }
+            | Expr::Reference { expr }
+            | Expr::Dereference { expr }
+            | Expr::Try { expr } => self.expr(expr),
+
+            Expr::Block { block } => self.sub_block(block),
+
+            Expr::If {
+                check,
+                block,
+                else_block,
+            } => {
+                self.expr(check);
+                self.maybe_block(block);
+
+                if let Some(else_block) = else_block {
+                    self.maybe(|resolver| resolver.expr(else_block));
+                }
+            }
+
+            Expr::FnCall {
+                ident,
+                params,
+                slot,
+                site,
+            } => {
+                if ident.inner == "format" {
+                    if let Some((fmt, args)) = params.split_first_mut() {
+                        let named = self.named_args(fmt, args.len());
+                        params.extend(named);
+                    }
+                }
+
+                for param in params.iter_mut() {
+                    self.expr(param);
+                }
+
+                *slot = self.lookup(ident.inner);
+                *site = self.site();
+            }
+
+            Expr::MethodCall {
+                ident,
+                caller,
+                params,
+                site,
+            } => {
+                if ident.inner == "format" {
+                    let named = self.named_args(caller, params.len());
+                    params.extend(named);
+                }
+
+                self.expr(caller);
+
+                for param in params {
+                    self.expr(param);
+                }
+
+                *site = self.site();
+            }
+
+            Expr::TryCatch {
+                try_block,
+                catch_block,
+            } => {
+                self.maybe_block(try_block);
+                self.maybe_block(catch_block);
+            }
+
+            Expr::WhileLoop { expr, block } => {
+                self.expr(expr);
+                self.sub_block(block);
+            }
+
+            Expr::ForLoop { ident, expr, block } => {
+                self.expr(expr);
+                self.sub(vec![ident.inner], |resolver| resolver.block(block));
+            }
+
+            Expr::Range { start, end, .. } => {
+                for bound in start.iter_mut().chain(end.iter_mut()) {
+                    self.expr(bound);
+                }
+            }
+
+            Expr::Match { expr, arms } => {
+                self.expr(expr);
+
+                for arm in arms {
+                    let idents = match &*arm.pattern {
+                        Pattern::Binding(ident) => vec![*ident],
+                        _ => Vec::new(),
+                    };
+
+                    self.sub(idents, |resolver| resolver.expr(&mut arm.expr));
+                }
+            }
+        }
+    }
+
+    /// Binds the variables a literal format string names, eg. `{hp}`, returning them
+    /// as the arguments to pass after the `argc` given ones, since `format` can't find
+    /// them by name at runtime.
+    fn named_args(&self, fmt: &mut Spanned<Expr>, argc: usize) -> Vec<Spanned<Expr>> {
+        let span = fmt.span;
+
+        let literal = match &mut **fmt {
+            Expr::Literal {
+                variant: Union::String(literal),
+            } => literal,
+            _ => return Vec::new(),
+        };
+
+        let bind = |ident: &str| match Symbol::get(ident) {
+            Some(ident) => !matches!(self.find(ident), Found::Nowhere),
+            None => false,
+        };
+
+        match bind_named_args(literal, argc, bind) {
+            Some((bound, idents)) => {
+                *literal = Shared::new(bound);
+
+                // resolved along with the other arguments
+                idents
+                    .into_iter()
+                    .map(|ident| Spanned {
+                        inner: Expr::Variable {
+                            ident: Spanned {
+                                inner: ident.into(),
+                                span,
+                            },
+                            slot: None,
+                        },
+                        span,
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::*;
+    use crate::test_util::*;
+
+    fn slots(source: &str) -> Vec<Option<Slot>> {
+        let mut program = crate::grammar::BlockParser::new().parse(source).unwrap();
+        resolve(&mut program);
+
+        let mut slots = Vec::new();
+
+        for stmt in &program.stmts {
+            if let Stmt::Let { expr, .. } = &**stmt {
+                if let Expr::Variable { slot, .. } = &**expr {
+                    slots.push(*slot);
+                }
+            }
+        }
+
+        slots
+    }
+
+    #[test]
+    fn slots_and_scopes() {
+        assert_eq!(
+            slots("let a = 1; let b = a; let a = b; let c = a; let d = nope;"),
+            vec![
+                Some(Slot { depth: 0, index: 0 }),
+                Some(Slot { depth: 0, index: 1 }),
+                Some(Slot { depth: 0, index: 2 }),
+                None,
+            ]
+        );
+
+        assert_eq!(eval("let v = 1; if true { let v = 2; } v"), "2");
+        assert!(try_eval("if false { let w = 2; } w").is_err());
+        assert_eq!(
+            eval("let a = 1; try { let b = 2; nope(); } catch { } let c = 3; a + c"),
+            "4"
+        );
+        assert_eq!(
+            eval("let hp = 7; let f = || format(\"{hp:>3}|{}\", hp + 1); f()"),
+            "  7|8"
+        );
+    }
+
+    #[test]
+    fn block_scoping() {
+        let undefined = |source: &str| {
+            matches!(
+                try_eval(source).unwrap_err().kind,
+                ErrorKind::UndefinedVariable
+            )
+        };
+
+        // `if` and `try` blocks declare into the enclosing scope
+        assert_eq!(eval("if true { let q = 1; } q"), "1");
+        assert_eq!(eval("try { let q = 1; } catch { } q"), "1");
+        assert_eq!(eval("try { nope(); } catch { let q = 1; } q"), "1");
+        assert_eq!(eval("let q = 0; if true { let q = 1; q += 1; } q"), "2");
+        assert_eq!(
+            eval("let r = 1; if true { let q = 2; } let s = 3; r + q + s"),
+            "6"
+        );
+
+        // unless they never ran
+        assert!(undefined("if false { let q = 1; } q"));
+
+        // `while` bodies, `else` blocks and plain blocks keep their own scope
+        assert!(undefined("if false { } else { let q = 1; } q"));
+        assert!(undefined("let i = 0; while i < 1 { let q = i; i += 1; } q"));
+        assert!(undefined("{ let q = 1; } q"));
+        assert_eq!(eval("let q = 0; { let q = 1; } q"), "0");
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::SystemTime;
+
+/// xoshiro256** pseudo random number generator, the same seed produces the same
+/// sequence on every platform.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: [u64; 4],
+}
+
+/// Used to expand a single seed into the generator's state.
+fn splitmix64(x: &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+    z ^ (z >> 31)
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        let mut x = seed;
+
+        Self {
+            state: [
+                splitmix64(&mut x),
+                splitmix64(&mut x),
+                splitmix64(&mut x),
+                splitmix64(&mut x),
+            ],
+        }
+    }
+
+    /// Seeds from the system clock, for when no seed was given.
+    pub fn from_entropy() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_nanos() as u64)
+            .unwrap_or(0);
+
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    /// Uniform integer in `0..bound`, `bound` must not be zero.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        // rejects the values that would make the lower results more likely
+        let threshold = bound.wrapping_neg() % bound;
+
+        loop {
+            let x = self.next_u64();
+
+            if x >= threshold {
+                return x % bound;
+            }
+        }
+    }
+
+    /// Uniform integer in `lo..=hi`.
+    pub fn int_in(&mut self, lo: i32, hi: i32) -> i32 {
+        let span = (hi as i64 - lo as i64 + 1) as u64;
+
+        (lo as i64 + self.below(span) as i64) as i32
+    }
+
+    /// Uniform float in `0.0..1.0`.
+    pub fn float(&mut self) -> f32 {
+        // the top 24 bits fill the mantissa exactly
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
+
+    pub fn shuffle<I>(&mut self, items: &mut [I]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+
+        // pinned so the sequence can't change between platforms or versions
+        assert_eq!(Rng::new(0).next_u64(), 0x99ec_5f36_cb75_f2b4);
+
+        for _ in 0..100 {
+            let i = a.int_in(-2, 2);
+            assert!((-2..=2).contains(&i));
+
+            let f = a.float();
+            assert!((0.0..1.0).contains(&f));
+        }
+    }
+}
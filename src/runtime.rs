@@ -1,36 +1,100 @@
 use crate::ast::*;
+use crate::call_cache::*;
+use crate::cancellation::*;
 use crate::control_flow::*;
 use crate::error::*;
 use crate::fn_storage::*;
+use crate::function::*;
+use crate::iron_std::regex::RegexCache;
+use crate::limits::*;
+use crate::rng::*;
 use crate::scope::*;
 use crate::span::*;
+use crate::symbol::*;
 use crate::to_fn_input::*;
 use crate::variant::*;
+use crate::vm::*;
 
 pub struct Runtime<'a, T> {
     pub ctx: &'a mut T,
     pub source: String,
+    /// Generator behind `std::rand`.
+    pub rng: Rng,
+    /// Patterns compiled by `std::regex`.
+    pub regex_cache: RegexCache,
+    pub limits: Limits,
+    /// Expressions and statements evaluated so far.
+    pub operations: u64,
+    /// Script functions and closures currently running.
+    pub call_depth: usize,
+    pub cancellation_token: Option<CancellationToken>,
+    pub on_progress: Option<ProgressFn>,
+    pub backend: Backend,
+    /// Value stacks of finished VM frames, reused instead of allocating one per call.
+    pub(crate) stacks: Vec<Vec<Variable>>,
+    /// What each [`CallSite`] dispatched to the last time.
+    pub(crate) call_caches: Vec<Option<CallCache<T>>>,
 }
 
 impl<'a, T> Runtime<'a, T> {
     pub fn new(ctx: &'a mut T, source: String) -> Self {
-        Self { ctx, source }
+        Self {
+            ctx,
+            source,
+            rng: Rng::from_entropy(),
+            regex_cache: RegexCache::default(),
+            limits: Limits::default(),
+            operations: 0,
+            call_depth: 0,
+            cancellation_token: None,
+            on_progress: None,
+            backend: Backend::default(),
+            stacks: Vec::new(),
+            call_caches: Vec::new(),
+        }
     }
 
+    /// Runs a program passed through [`resolve`](crate::resolve::resolve).
     pub fn run(&mut self, program: &Block, scope: &mut Scope<T>) -> Result<Union, Error> {
-        match self.eval_block(&program, scope) {
+        self.run_compiled(program, None, scope)
+    }
+
+    /// Like [`Runtime::run`], but the VM runs `chunk` if it was compiled from `program`
+    /// already, see [`Chunk::compile`].
+    pub fn run_compiled(
+        &mut self,
+        program: &Block,
+        chunk: Option<&Chunk>,
+        scope: &mut Scope<T>,
+    ) -> Result<Union, Error> {
+        // the program's variables start a function scope of their own
+        scope.sub(true);
+
+        let returned = match (self.backend, chunk) {
+            (Backend::TreeWalker, _) => self.eval_block(program, scope),
+            (Backend::Vm, Some(chunk)) => self.run_chunk(chunk, scope),
+            (Backend::Vm, None) => self.run_chunk(&Chunk::from_program(program), scope),
+        };
+
+        scope.rev_sub();
+
+        match returned {
             Ok(variable) | Err(ControlFlow::Return(variable)) => Ok(variable.into_inner()),
             Err(ControlFlow::Error(error)) => Err(error),
+            Err(ControlFlow::Exit(code)) => Err(Error::from_raw(
+                ErrorKind::Exit(code),
+                format!("exited with code {}", code),
+            )),
         }
     }
 
     #[inline(always)]
     pub fn call_fn<I: ToFnInput>(
         &mut self,
-        ident: impl Into<String>,
+        ident: impl Into<Symbol>,
         input: I,
         scope: &mut Scope<T>,
-    ) -> Result<Variable, Error> {
+    ) -> Result<Variable, ControlFlow> {
         let params = input.to_fn_parameters();
         let input = input.to_fn_input();
 
@@ -52,6 +116,91 @@ impl<'a, T> Runtime<'a, T> {
         fn_type.run(&Span::new(0, 0), self, scope, input)
     }
 
+    /// Turns `value` into an iterator by calling `into_iter` on it, values without an
+    /// `into_iter` are assumed to be iterators already.
+    pub fn into_iter(
+        &mut self,
+        value: Variable,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        let fn_signature = FnSignature {
+            ident: "into_iter".into(),
+            params: vec![value.ty()],
+        };
+
+        match scope.get_fn(&fn_signature) {
+            Ok(into_iter) => into_iter
+                .clone()
+                .run(&Span::new(0, 0), self, scope, vec![value]),
+            Err(_) => Ok(value),
+        }
+    }
+
+    /// Advances `iterator` by calling `iter_next` on a reference to it.
+    pub fn iter_next(
+        &mut self,
+        iterator: &mut UnionCell,
+        scope: &mut Scope<T>,
+    ) -> Result<Option<Union>, ControlFlow> {
+        let params = vec![Variable::specified(Union::Reference(Box::new(
+            Variable::specified(iterator.get_shared()),
+        )))];
+
+        self.call_fn("iter_next", params, scope)?
+            .into_inner()
+            .downcast::<Option<Union>>()
+            .ok_or_else(|| {
+                Error::from_raw(ErrorKind::TypeMismatch, "iter_next must return an option").into()
+            })
+    }
+
+    /// Applies the binary operator `op`, preferring registered overloads over the
+    /// built in operators on numbers.
+    #[inline(always)]
+    pub fn eval_binop(
+        &mut self,
+        op: &str,
+        span: Span,
+        lhs: Variable,
+        rhs: Variable,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        let fn_signature = FnSignature {
+            ident: op.into(),
+            params: vec![lhs.ty(), rhs.ty()],
+        };
+
+        let op_fn = scope.get_fn(&fn_signature).ok().cloned();
+
+        self.apply_binop(op_fn, op, span, lhs, rhs, scope)
+    }
+
+    /// Applies `op` with the overload `op_fn`, or the built in operator without one.
+    #[inline(always)]
+    pub(crate) fn apply_binop(
+        &mut self,
+        op_fn: Option<FnType<T>>,
+        op: &str,
+        span: Span,
+        lhs: Variable,
+        rhs: Variable,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        match op_fn {
+            Some(op_fn) => {
+                let params = vec![lhs, rhs];
+
+                op_fn.run(&span, self, scope, params.to_fn_input())
+            }
+            None => {
+                match crate::internal_binop::internal_binop(lhs.into_inner(), rhs.into_inner(), op) {
+                    Some(v) => Ok(Variable::specified(v)),
+                    None => Err(Error::new(ErrorKind::UndefinedFunction, &self.source, span).into()),
+                }
+            }
+        }
+    }
+
     #[inline(always)]
     pub fn eval_block(
         &mut self,
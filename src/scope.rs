@@ -2,12 +2,36 @@ use crate::error::ErrorKind;
 use crate::fn_storage::*;
 use crate::function::*;
 use crate::module::*;
+use crate::symbol::*;
 use crate::variant::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+fn next_generation() -> u64 {
+    static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+    GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Where a variable lives, `depth` sub scopes out from the current one and `index`
+/// variables into that sub scope. Filled in by [`resolve`](crate::resolve::resolve).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
 
 pub struct Scope<T> {
-    module: Module<T>,
+    /// Functions registered by the host, shared with every other run and only copied
+    /// when changed.
+    globals: Arc<Module<T>>,
+    /// Functions defined by the running script.
+    functions: FnStorage<T>,
+    generation: u64,
     values: Vec<Variable>,
-    idents: Vec<String>,
+    /// The name of each of `values`, for variables whose slot isn't known, see
+    /// [`Scope::find`].
+    idents: Vec<Symbol>,
     start: usize,
     subs: Vec<(usize, Option<usize>)>,
 }
@@ -15,10 +39,12 @@ pub struct Scope<T> {
 impl<T> Clone for Scope<T> {
     fn clone(&self) -> Self {
         Self {
-            module: self.module.clone(),
+            globals: self.globals.clone(),
+            functions: self.functions.clone(),
+            generation: self.generation,
             values: self.values.clone(),
             idents: self.idents.clone(),
-            start: self.start.clone(),
+            start: self.start,
             subs: self.subs.clone(),
         }
     }
@@ -26,8 +52,15 @@ impl<T> Clone for Scope<T> {
 
 impl<T> Scope<T> {
     pub fn new() -> Self {
+        Self::with_globals(Arc::new(Module::new()))
+    }
+
+    /// Creates a scope calling the functions in `globals`, without copying them.
+    pub fn with_globals(globals: Arc<Module<T>>) -> Self {
         Self {
-            module: Module::new(),
+            globals,
+            functions: FnStorage::new(),
+            generation: next_generation(),
             values: Vec::with_capacity(64),
             idents: Vec::with_capacity(64),
             start: 0,
@@ -36,7 +69,8 @@ impl<T> Scope<T> {
     }
 
     pub fn merge_module(&mut self, module: Module<T>) {
-        self.module.merge_module(module);
+        self.generation = next_generation();
+        Arc::make_mut(&mut self.globals).merge_module(module);
     }
 
     pub fn register_module(
@@ -44,53 +78,110 @@ impl<T> Scope<T> {
         ident: impl Into<String>,
         module: Module<T>,
     ) -> Option<Module<T>> {
-        self.module.register_sub_module(ident, module)
+        self.generation = next_generation();
+        Arc::make_mut(&mut self.globals).register_sub_module(ident, module)
     }
 
+    /// Defines a function for the rest of the run, it can't replace one of the globals.
     pub fn register_fn(
         &mut self,
         signature: FnSignature,
         fn_type: FnType<T>,
     ) -> Result<(), ErrorKind> {
-        self.module.register_fn_raw(signature, fn_type)
+        self.generation = next_generation();
+
+        if self.globals.functions().contains(&signature) {
+            return Err(ErrorKind::FunctionRedefinition);
+        }
+
+        self.functions.register_fn(signature, fn_type)
+    }
+
+    pub fn remove_fn(&mut self, path: &str) -> bool {
+        self.generation = next_generation();
+
+        let defined = !path.contains("::") && self.functions.remove_fn(path);
+
+        Arc::make_mut(&mut self.globals).remove_fn(path) || defined
+    }
+
+    /// Identifies the functions this scope can call, it changes whenever they do and is
+    /// never shared by scopes with different functions.
+    #[inline(always)]
+    pub fn generation(&self) -> u64 {
+        self.generation
     }
 
     #[inline(always)]
     pub fn get_fn(&self, signature: &FnSignature) -> Result<&FnType<T>, ErrorKind> {
-        self.module.get_fn(signature)
+        if signature.ident.contains("::") {
+            self.globals.get_fn(signature)
+        } else {
+            self.functions
+                .get_fn_over(self.globals.functions(), signature.ident, &signature.params)
+        }
     }
 
-    pub fn push(&mut self, ident: impl Into<String>, value: impl Into<Variable>) {
-        self.idents.push(ident.into());
+    /// Adds a variable to the current sub scope, it's found by the next free [`Slot`].
+    pub fn push(&mut self, ident: Symbol, value: impl Into<Variable>) {
         self.values.push(value.into());
+        self.idents.push(ident);
+    }
+
+    /// Number of variables alive, including those hidden from the current function.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
     }
 
-    pub fn get_index(&self, ident: &String) -> Option<usize> {
-        self.idents[self.start..]
+    /// Gets shared handles to every variable visible from the current function, in the
+    /// order they were pushed.
+    pub fn capture(&mut self) -> Vec<(Symbol, Variable)> {
+        let start = self.start;
+
+        self.idents[start..]
             .iter()
-            .enumerate()
-            .rev()
-            .find_map(|(i, name)| {
-                if name == ident {
-                    Some(self.start + i)
-                } else {
-                    None
-                }
-            })
-    }
-
-    pub fn get_variable(&self, ident: &String) -> Option<&Variable> {
-        match self.get_index(ident) {
-            Some(index) => Some(&self.values[index]),
-            None => None,
-        }
+            .copied()
+            .zip(self.values[start..].iter_mut().map(Variable::get_shared))
+            .collect()
     }
 
-    pub fn get_variable_mut(&mut self, ident: &String) -> Option<&mut Variable> {
-        match self.get_index(ident) {
-            Some(index) => Some(&mut self.values[index]),
-            None => None,
-        }
+    /// Finds the latest variable called `ident` in the current function by name, for
+    /// the ones declared in blocks that may not have run, which have no fixed slot.
+    pub fn find(&mut self, ident: Symbol) -> Option<&mut Variable> {
+        let start = self.start;
+        let index = self.idents[start..]
+            .iter()
+            .rposition(|name| *name == ident)?;
+
+        Some(&mut self.values[start + index])
+    }
+
+    #[inline(always)]
+    fn index(&self, slot: Slot) -> usize {
+        let (base, _) = self.subs[self.subs.len() - 1 - slot.depth];
+
+        base + slot.index
+    }
+
+    #[inline(always)]
+    pub fn get(&self, slot: Slot) -> &Variable {
+        &self.values[self.index(slot)]
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self, slot: Slot) -> &mut Variable {
+        let index = self.index(slot);
+
+        &mut self.values[index]
+    }
+
+    /// Number of open sub scopes, see [`Scope::rev_sub_to`].
+    pub fn depth(&self) -> usize {
+        self.subs.len()
     }
 
     #[inline(always)]
@@ -114,4 +205,11 @@ impl<T> Scope<T> {
             self.start = start;
         }
     }
+
+    /// Closes sub scopes until `depth` are left open, eg. the ones an error skipped.
+    pub fn rev_sub_to(&mut self, depth: usize) {
+        while self.subs.len() > depth {
+            self.rev_sub();
+        }
+    }
 }
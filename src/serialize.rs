@@ -0,0 +1,709 @@
+//! Conversions between serde types and [`Union`]s, enabled by the `serde` feature.
+//!
+//! Structs and maps become [`Map`]s, sequences and tuples become arrays and enums are
+//! externally tagged like in JSON: unit variants are their name as a string, other
+//! variants a single entry map from their name to their content. Integers that don't
+//! fit in a script int are an error rather than a lossy float.
+
+use crate::error::*;
+use crate::variant::*;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use std::convert::TryFrom;
+
+fn serde_error(message: impl std::fmt::Display) -> Error {
+    Error::from_raw(ErrorKind::InvalidArgument, message.to_string())
+}
+
+impl ser::Error for Error {
+    fn custom<M: std::fmt::Display>(message: M) -> Self {
+        serde_error(message)
+    }
+}
+
+impl de::Error for Error {
+    fn custom<M: std::fmt::Display>(message: M) -> Self {
+        serde_error(message)
+    }
+}
+
+/// Serializes `value` into a script value.
+pub fn to_union<T: Serialize + ?Sized>(value: &T) -> Result<Union, Error> {
+    value.serialize(UnionSerializer)
+}
+
+/// Deserializes a script value, eg. one returned by a script, into `T`.
+pub fn from_union<T: DeserializeOwned>(union: Union) -> Result<T, Error> {
+    T::deserialize(UnionDeserializer(union))
+}
+
+fn int<I: Copy + std::fmt::Display>(i: I) -> Result<Union, Error>
+where
+    i32: TryFrom<I>,
+{
+    i32::try_from(i)
+        .map(Union::Int)
+        .map_err(|_| serde_error(format!("{} doesn't fit in an int", i)))
+}
+
+fn tagged(variant: &str, content: Union) -> Union {
+    let mut map = Map::new();
+    map.insert(variant.to_string(), UnionCell::from(content));
+
+    Union::from(map)
+}
+
+struct UnionSerializer;
+
+impl ser::Serializer for UnionSerializer {
+    type Ok = Union;
+    type Error = Error;
+
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Union, Error> {
+        Ok(Union::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Union, Error> {
+        Ok(Union::Int(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Union, Error> {
+        Ok(Union::Int(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Union, Error> {
+        Ok(Union::Int(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Union, Error> {
+        int(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Union, Error> {
+        Ok(Union::Int(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Union, Error> {
+        Ok(Union::Int(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Union, Error> {
+        int(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Union, Error> {
+        int(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Union, Error> {
+        Ok(Union::Float(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Union, Error> {
+        Ok(Union::Float(v as f32))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Union, Error> {
+        Ok(Union::from(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Union, Error> {
+        Ok(Union::from(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Union, Error> {
+        Ok(Union::Blob(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Union, Error> {
+        Ok(Union::Unit(()))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Union, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Union, Error> {
+        Ok(Union::Unit(()))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Union, Error> {
+        Ok(Union::Unit(()))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Union, Error> {
+        Ok(Union::from(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Union, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Union, Error> {
+        Ok(tagged(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            map: Map::new(),
+            key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            map: Map::new(),
+            key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+struct SeqSerializer {
+    items: Vec<UnionCell>,
+    variant: Option<&'static str>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(UnionCell::from(to_union(value)?));
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Union, Error> {
+        let items = Union::from(self.items);
+
+        Ok(match self.variant {
+            Some(variant) => tagged(variant, items),
+            None => items,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Union;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Union, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Union;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Union, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Union;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Union, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Union;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Union, Error> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    map: Map,
+    key: Option<String>,
+    variant: Option<&'static str>,
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), Error> {
+        self.map.insert(key, UnionCell::from(to_union(value)?));
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Union, Error> {
+        let map = Union::from(self.map);
+
+        Ok(match self.variant {
+            Some(variant) => tagged(variant, map),
+            None => map,
+        })
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Union;
+    type Error = Error;
+
+    // script maps are keyed by strings, so other keys are stringified like JSON does
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        let key = match to_union(key)? {
+            key @ Union::String(_) | key @ Union::Int(_) | key @ Union::Bool(_) => key.to_string(),
+            key => return Err(serde_error(format!("{} can't be a map key", key.ty()))),
+        };
+
+        self.key = Some(key);
+
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| serde_error("map value without a key"))?;
+
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Union, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Union;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Union, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Union;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Union, Error> {
+        self.finish()
+    }
+}
+
+struct UnionDeserializer(Union);
+
+impl UnionDeserializer {
+    /// Follows references and unwraps options down to the value itself.
+    fn resolve(self) -> Union {
+        let mut union = self.0;
+
+        loop {
+            union = match union {
+                Union::Reference(variable) => variable.union.into_inner(),
+                Union::Variant(variant) if Variant::as_any(&*variant).is::<Option<Union>>() => {
+                    match Union::Variant(variant).downcast::<Option<Union>>() {
+                        Some(Some(union)) => union,
+                        _ => Union::Unit(()),
+                    }
+                }
+                union => return union,
+            };
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for UnionDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for UnionDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.resolve() {
+            Union::Int(i) => visitor.visit_i32(i),
+            Union::Float(f) => visitor.visit_f32(f),
+            Union::Bool(b) => visitor.visit_bool(b),
+            Union::String(s) => visitor.visit_string(s.to_string()),
+            Union::Unit(()) => visitor.visit_unit(),
+            Union::Blob(b) => visitor.visit_byte_buf(b),
+            union @ Union::Variant(_) => {
+                if let Some(items) = union.downcast_ref::<Vec<UnionCell>>() {
+                    let items = items.iter().map(|item| UnionDeserializer(item.cloned()));
+
+                    visitor.visit_seq(de::value::SeqDeserializer::new(items))
+                } else if let Some(map) = union.downcast_ref::<Map>() {
+                    let entries = map.iter().map(|(key, value)| {
+                        (
+                            KeyDeserializer(key.clone()),
+                            UnionDeserializer(value.cloned()),
+                        )
+                    });
+
+                    visitor.visit_map(de::value::MapDeserializer::new(entries))
+                } else {
+                    Err(serde_error(format!("can't deserialize {}", union)))
+                }
+            }
+            union => Err(serde_error(format!(
+                "can't deserialize {} of type {}",
+                union,
+                union.ty()
+            ))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.resolve() {
+            Union::Unit(()) => visitor.visit_none(),
+            union => visitor.visit_some(UnionDeserializer(union)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.resolve() {
+            Union::String(variant) => visitor.visit_enum(variant.to_string().into_deserializer()),
+            union => {
+                let (variant, content) = union
+                    .downcast_ref::<Map>()
+                    .filter(|map| map.len() == 1)
+                    .and_then(|map| map.iter().next())
+                    .map(|(variant, content)| (variant.clone(), content.cloned()))
+                    .ok_or_else(|| serde_error(format!("{} is not an enum variant", union)))?;
+
+                visitor.visit_enum(EnumDeserializer { variant, content })
+            }
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+macro_rules! parse_key {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.0.parse() {
+                    Ok(key) => visitor.$visit(key),
+                    Err(_) => Err(de::Error::invalid_value(de::Unexpected::Str(&self.0), &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+/// Deserializes a map key, parsing back the ints and bools `serialize_key` stringified.
+struct KeyDeserializer(String);
+
+impl<'de> IntoDeserializer<'de, Error> for KeyDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.0)
+    }
+
+    parse_key! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_enum(
+            IntoDeserializer::<Error>::into_deserializer(self.0),
+            name,
+            variants,
+            visitor,
+        )
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 f32 f64 char str string bytes byte_buf option unit unit_struct
+        seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    content: Union,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = UnionDeserializer;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, UnionDeserializer), Error> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+
+        Ok((variant, UnionDeserializer(self.content)))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for UnionDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::*;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Point,
+        Circle(f32),
+        Rect { w: i32, h: i32 },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        retries: u8,
+        ratio: Option<f32>,
+        tags: Vec<String>,
+        shapes: Vec<Shape>,
+        limits: HashMap<String, i64>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let config = Config {
+            name: "orc".into(),
+            retries: 3,
+            ratio: None,
+            tags: vec!["a".into(), "b".into()],
+            shapes: vec![Shape::Point, Shape::Circle(1.5), Shape::Rect { w: 2, h: 3 }],
+            limits: vec![("hp".to_string(), 10)].into_iter().collect(),
+        };
+
+        let union = to_union(&config).unwrap();
+
+        assert_eq!(from_union::<Config>(union).unwrap(), config);
+        assert!(from_union::<Config>(Union::Int(1)).is_err());
+    }
+
+    #[test]
+    fn lossless_ints() {
+        assert!(to_union(&(1i64 << 40)).is_err());
+        assert!(to_union(&u32::MAX).is_err());
+        assert!(to_union(&u64::MAX).is_err());
+
+        assert_eq!(from_union::<i64>(to_union(&-5i64).unwrap()).unwrap(), -5);
+        assert_eq!(from_union::<u32>(to_union(&7u32).unwrap()).unwrap(), 7);
+        assert_eq!(from_union::<u64>(to_union(&8u64).unwrap()).unwrap(), 8);
+        assert!(from_union::<u32>(Union::Int(-1)).is_err());
+    }
+
+    #[test]
+    fn non_string_keys() {
+        let ints: HashMap<i32, i32> = vec![(1, 2), (-3, 4)].into_iter().collect();
+        let union = to_union(&ints).unwrap();
+
+        assert_eq!(from_union::<HashMap<i32, i32>>(union).unwrap(), ints);
+
+        let bools: HashMap<bool, String> = vec![(true, "yes".to_string())].into_iter().collect();
+        let union = to_union(&bools).unwrap();
+
+        assert_eq!(from_union::<HashMap<bool, String>>(union).unwrap(), bools);
+
+        let mut names = Map::new();
+        names.insert("x".to_string(), UnionCell::from(Union::Int(1)));
+
+        assert!(from_union::<HashMap<i32, i32>>(Union::from(names)).is_err());
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Stats {
+        hp: i32,
+        class: Shape,
+    }
+
+    #[test]
+    fn from_script() {
+        let engine = Engine::<()>::new();
+
+        let union = engine
+            .eval(
+                &mut (),
+                r#"let m = map(); m.insert("hp", 5 * 2); m.insert("class", "Point"); m"#,
+            )
+            .unwrap();
+
+        assert_eq!(
+            from_union::<Stats>(union).unwrap(),
+            Stats {
+                hp: 10,
+                class: Shape::Point
+            }
+        );
+    }
+}
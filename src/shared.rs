@@ -0,0 +1,123 @@
+//! The pointers behind shared values.
+//!
+//! By default values are shared through `Arc<RwLock<..>>` and every [`Variant`] has to
+//! be `Send + Sync`. The `unsync` feature switches to `Rc<RefCell<..>>`, which skips
+//! the atomic operations and lets hosts register types that can't leave their thread,
+//! eg. GUI handles.
+//!
+//! Functions registered with the host aren't required to be `Send + Sync`, unless the
+//! `sync` feature is enabled, which makes an [`Engine`] shareable between threads.
+//!
+//! `unsync` takes precedence: with both features enabled, eg. by `--all-features` or
+//! two dependents asking for one each, values are shared through `Rc` and `sync` does
+//! nothing.
+//!
+//! [`Engine`]: crate::engine::Engine
+//! [`Variant`]: crate::variant::Variant
+
+#[cfg(not(feature = "unsync"))]
+mod imp {
+    pub type Shared<T> = std::sync::Arc<T>;
+    pub type Lock<T> = std::sync::RwLock<T>;
+    pub type ReadGuard<'a, T> = std::sync::RwLockReadGuard<'a, T>;
+    pub type WriteGuard<'a, T> = std::sync::RwLockWriteGuard<'a, T>;
+
+    #[inline(always)]
+    pub fn read<T>(lock: &Lock<T>) -> ReadGuard<'_, T> {
+        lock.read().expect("Dead Lock")
+    }
+
+    #[inline(always)]
+    pub fn write<T>(lock: &Lock<T>) -> WriteGuard<'_, T> {
+        lock.write().expect("Dead Lock")
+    }
+
+    /// Implemented for every type that's `Send + Sync`.
+    pub trait SendSync: Send + Sync {}
+
+    impl<T: Send + Sync> SendSync for T {}
+}
+
+#[cfg(feature = "unsync")]
+mod imp {
+    pub type Shared<T> = std::rc::Rc<T>;
+    pub type Lock<T> = std::cell::RefCell<T>;
+    pub type ReadGuard<'a, T> = std::cell::Ref<'a, T>;
+    pub type WriteGuard<'a, T> = std::cell::RefMut<'a, T>;
+
+    #[inline(always)]
+    pub fn read<T>(lock: &Lock<T>) -> ReadGuard<'_, T> {
+        lock.borrow()
+    }
+
+    #[inline(always)]
+    pub fn write<T>(lock: &Lock<T>) -> WriteGuard<'_, T> {
+        lock.borrow_mut()
+    }
+
+    /// Implemented for every type, threads aren't a concern with `unsync`.
+    pub trait SendSync {}
+
+    impl<T> SendSync for T {}
+}
+
+pub use imp::*;
+
+/// `Send + Sync` with the `sync` feature, unless `unsync` overrides it, implemented for
+/// every type otherwise.
+#[cfg(all(feature = "sync", not(feature = "unsync")))]
+pub trait MaybeSync: Send + Sync {}
+
+#[cfg(all(feature = "sync", not(feature = "unsync")))]
+impl<T: Send + Sync> MaybeSync for T {}
+
+/// `Send + Sync` with the `sync` feature, unless `unsync` overrides it, implemented for
+/// every type otherwise.
+#[cfg(any(not(feature = "sync"), feature = "unsync"))]
+pub trait MaybeSync {}
+
+#[cfg(any(not(feature = "sync"), feature = "unsync"))]
+impl<T> MaybeSync for T {}
+
+/// Declares a trait object type, which is `Send + Sync` when [`MaybeSync`] is.
+macro_rules! maybe_sync_dyn {
+    (
+        $(#[$meta:meta])*
+        $vis:vis type $ident:ident $(<$($generic:ident),*>)? = dyn $bound:path;
+    ) => {
+        #[cfg(any(not(feature = "sync"), feature = "unsync"))]
+        $(#[$meta])*
+        $vis type $ident $(<$($generic),*>)? = dyn $bound;
+
+        #[cfg(all(feature = "sync", not(feature = "unsync")))]
+        $(#[$meta])*
+        $vis type $ident $(<$($generic),*>)? = dyn $bound + Send + Sync;
+    };
+}
+
+pub(crate) use maybe_sync_dyn;
+
+#[cfg(all(test, feature = "unsync"))]
+mod test {
+    use crate::engine::*;
+    use crate::test_util::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn non_send_types() {
+        let mut engine = Engine::<()>::new();
+        engine.register_fn("counter", || Rc::new(Cell::new(0)));
+        engine.register_fn("bump", |counter: &mut Rc<Cell<i32>>| {
+            counter.set(counter.get() + 1);
+            counter.get()
+        });
+
+        let eval = |source: &str| eval_with(&engine, source);
+
+        assert_eq!(
+            eval("let c = counter(); let d = &c; c.bump(); d.bump()"),
+            "2"
+        );
+    }
+}
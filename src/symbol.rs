@@ -0,0 +1,140 @@
+use fnv::FnvHashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{OnceLock, RwLock};
+
+/// An interned identifier, copying, comparing and hashing it costs as much as for a
+/// pointer.
+///
+/// Interned strings are leaked, they live as long as the program and are shared by
+/// every engine. Every distinct identifier in a compiled source or registered name
+/// stays interned, so a host compiling endless generated sources grows the interner
+/// with each new name. Lookups by a name go through [`Symbol::get`], which doesn't
+/// intern it.
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+fn interner() -> &'static RwLock<FnvHashSet<&'static str>> {
+    static INTERNER: OnceLock<RwLock<FnvHashSet<&'static str>>> = OnceLock::new();
+
+    INTERNER.get_or_init(Default::default)
+}
+
+impl Symbol {
+    pub fn intern(s: &str) -> Self {
+        if let Some(interned) = interner().read().unwrap().get(s) {
+            return Symbol(interned);
+        }
+
+        let mut interner = interner().write().unwrap();
+
+        // another thread may have interned it in the meantime
+        if let Some(interned) = interner.get(s) {
+            return Symbol(interned);
+        }
+
+        let interned: &'static str = Box::leak(s.into());
+        interner.insert(interned);
+
+        Symbol(interned)
+    }
+
+    /// Finds the symbol for `s` if it was interned, without interning it. Nothing can
+    /// be registered under a name that was never interned.
+    pub fn get(s: &str) -> Option<Self> {
+        interner()
+            .read()
+            .unwrap()
+            .get(s)
+            .map(|interned| Symbol(interned))
+    }
+
+    #[inline(always)]
+    pub fn as_str(self) -> &'static str {
+        self.0
+    }
+}
+
+// every string is interned once, so its address identifies it
+impl PartialEq for Symbol {
+    #[inline(always)]
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    #[inline(always)]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0.as_ptr() as usize).hash(state);
+    }
+}
+
+impl std::ops::Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Self {
+        Self::intern(s)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(s: &String) -> Self {
+        Self::intern(s)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(s: String) -> Self {
+        Self::intern(&s)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn intern() {
+        let a = Symbol::intern("symbol_test");
+
+        assert_eq!(a, Symbol::from(String::from("symbol_test")));
+        assert_ne!(a, Symbol::intern("symbol_test2"));
+        assert_eq!(Symbol::get("symbol_test"), Some(a));
+        assert_eq!(Symbol::get("symbol_test_never_interned"), None);
+        assert_eq!(a.as_str(), "symbol_test");
+        assert_eq!(format!("{} {:?}", a, a), "symbol_test \"symbol_test\"");
+    }
+}
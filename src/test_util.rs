@@ -0,0 +1,23 @@
+//! Shorthands for tests, which mostly run a snippet and compare what it shows.
+
+use crate::engine::*;
+use crate::error::*;
+
+thread_local! {
+    static ENGINE: Engine<()> = Engine::new();
+}
+
+/// Runs `source` with a default engine and shows its result.
+pub fn try_eval(source: &str) -> Result<String, Error> {
+    ENGINE.with(|engine| engine.eval(&mut (), source).map(|u| u.to_string()))
+}
+
+/// Runs `source` with a default engine and shows its result, panicking on errors.
+pub fn eval(source: &str) -> String {
+    try_eval(source).unwrap()
+}
+
+/// Runs `source` with `engine` and shows its result, panicking on errors.
+pub fn eval_with(engine: &Engine<()>, source: &str) -> String {
+    engine.eval(&mut (), source).unwrap().to_string()
+}
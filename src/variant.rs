@@ -1,7 +1,9 @@
+use crate::range::*;
+use crate::shared::*;
 use std::any::{Any, TypeId};
-use std::sync::{Arc, RwLock};
+use std::collections::BTreeMap;
 
-pub trait Variant: Any + Sync + Send + 'static {
+pub trait Variant: Any + SendSync + 'static {
     fn type_id(&self) -> TypeId;
 
     fn as_any(&self) -> &dyn Any;
@@ -13,7 +15,7 @@ pub trait Variant: Any + Sync + Send + 'static {
     fn type_name(&self) -> &str;
 }
 
-impl<T: Clone + Send + Sync + Any> Variant for T {
+impl<T: Clone + SendSync + Any> Variant for T {
     #[inline(always)]
     fn type_id(&self) -> TypeId {
         TypeId::of::<T>()
@@ -66,16 +68,24 @@ fn unsafe_try_cast<A: Any, B: Any>(a: A) -> Option<B> {
     }
 }
 
-pub type SharedString = Arc<String>;
+pub type SharedString = Shared<String>;
+
+/// String keyed map, what JSON objects and serialized structs become in scripts.
+pub type Map = BTreeMap<String, UnionCell>;
+
+/// Byte buffer, what `b"..."` literals create in scripts.
+pub type Blob = Vec<u8>;
 
 #[derive(Debug)]
 pub enum Union {
     Int(i32),
     Float(f32),
     Bool(bool),
-    String(Arc<String>),
+    String(SharedString),
     Unit(()),
     Type(UnionType),
+    Range(Range),
+    Blob(Blob),
     Reference(Box<Variable>),
     Variant(Box<dyn Variant>),
 }
@@ -91,6 +101,8 @@ impl std::fmt::Display for Union {
             Self::Reference(v) => write!(f, "{}", v.cloned())?,
             Self::Unit(_) => write!(f, "()")?,
             Self::Type(t) => write!(f, "{}", t)?,
+            Self::Range(r) => write!(f, "{}", r)?,
+            Self::Blob(b) => write!(f, "b\"{}\"", b.escape_ascii())?,
             Self::Variant(v) => write!(f, "variant<{}>", v.as_ref().type_name())?,
         }
 
@@ -109,6 +121,8 @@ impl Clone for Union {
             Self::Reference(r) => Self::Reference(Box::new(r.clone_shared())),
             Self::Unit(()) => Self::Unit(()),
             Self::Type(t) => Self::Type(t.clone()),
+            Self::Range(r) => Self::Range(*r),
+            Self::Blob(b) => Self::Blob(b.clone()),
             Self::Variant(v) => Variant::clone_into_union(&**v),
         }
     }
@@ -143,7 +157,7 @@ impl Union {
         }
 
         if variant.as_any().type_id() == TypeId::of::<String>() {
-            return Self::String(Arc::new(unsafe_try_cast(variant).unwrap()));
+            return Self::String(Shared::new(unsafe_try_cast(variant).unwrap()));
         }
 
         // unit
@@ -156,6 +170,16 @@ impl Union {
             return Self::Type(unsafe_try_cast(variant).unwrap());
         }
 
+        // range
+        if variant.as_any().type_id() == TypeId::of::<Range>() {
+            return Self::Range(unsafe_try_cast(variant).unwrap());
+        }
+
+        // blob
+        if variant.as_any().type_id() == TypeId::of::<Blob>() {
+            return Self::Blob(unsafe_try_cast(variant).unwrap());
+        }
+
         // variant
         Self::Variant(Box::new(variant))
     }
@@ -237,12 +261,33 @@ impl Union {
             };
         }
 
+        // range
+        if TypeId::of::<T>() == TypeId::of::<Range>() {
+            return match self {
+                Self::Range(v) => unsafe_try_cast(v),
+                _ => None,
+            };
+        }
+
+        // blob
+        if TypeId::of::<T>() == TypeId::of::<Blob>() {
+            return match self {
+                Self::Blob(v) => unsafe_try_cast(v),
+                _ => None,
+            };
+        }
+
         // variant
         match self {
             Self::Variant(variant) => {
-                let b: Option<Box<T>> = unsafe_try_cast(variant);
+                if Variant::as_any(&*variant).is::<T>() {
+                    // SAFETY: we just checked that the boxed value is a T
+                    let raw = Box::into_raw(variant) as *mut T;
 
-                b.map(|inner| *inner)
+                    Some(*unsafe { Box::from_raw(raw) })
+                } else {
+                    None
+                }
             }
             _ => None,
         }
@@ -312,6 +357,22 @@ impl Union {
             };
         }
 
+        // range
+        if TypeId::of::<T>() == TypeId::of::<Range>() {
+            return match self {
+                Self::Range(v) => <dyn Any>::downcast_ref(v),
+                _ => None,
+            };
+        }
+
+        // blob
+        if TypeId::of::<T>() == TypeId::of::<Blob>() {
+            return match self {
+                Self::Blob(v) => <dyn Any>::downcast_ref(v),
+                _ => None,
+            };
+        }
+
         // variant
         match self {
             Self::Variant(variant) => <dyn Any>::downcast_ref(variant.as_ref().as_any()),
@@ -354,7 +415,7 @@ impl Union {
         if TypeId::of::<T>() == TypeId::of::<String>() {
             return match self {
                 Self::String(v) => {
-                    let string: &mut String = Arc::make_mut(v);
+                    let string: &mut String = Shared::make_mut(v);
                     <dyn Any>::downcast_mut(string)
                 }
                 _ => None,
@@ -384,6 +445,22 @@ impl Union {
             };
         }
 
+        // range
+        if TypeId::of::<T>() == TypeId::of::<Range>() {
+            return match self {
+                Self::Range(v) => <dyn Any>::downcast_mut(v),
+                _ => None,
+            };
+        }
+
+        // blob
+        if TypeId::of::<T>() == TypeId::of::<Blob>() {
+            return match self {
+                Self::Blob(v) => <dyn Any>::downcast_mut(v),
+                _ => None,
+            };
+        }
+
         // variant
         match self {
             Self::Variant(variant) => <dyn Any>::downcast_mut(variant.as_mut().as_mut_any()),
@@ -401,6 +478,8 @@ impl Union {
             Self::Reference(value) => UnionType::Reference(Box::new(value.ty())),
             Self::Unit(_) => UnionType::Unit,
             Self::Type(_) => UnionType::Type,
+            Self::Range(_) => UnionType::Range,
+            Self::Blob(_) => UnionType::Blob,
             Self::Variant(variant) => UnionType::Variant(Variant::as_any(&**variant).type_id()),
         }
     }
@@ -440,6 +519,8 @@ pub enum UnionType {
     Reference(Box<UnionType>),
     Unit,
     Type,
+    Range,
+    Blob,
     Variant(TypeId),
     Any,
 }
@@ -455,6 +536,8 @@ impl std::fmt::Display for UnionType {
             Self::Reference(ty) => write!(f, "&{}", ty)?,
             Self::Unit => write!(f, "()")?,
             Self::Type => write!(f, "type")?,
+            Self::Range => write!(f, "Range")?,
+            Self::Blob => write!(f, "Blob")?,
             Self::Variant(type_id) => write!(f, "variant<{:?}>", type_id)?,
             Self::Any => write!(f, "any")?,
         }
@@ -513,13 +596,23 @@ impl UnionType {
             return Self::Type;
         }
 
+        // range
+        if TypeId::of::<T>() == TypeId::of::<Range>() {
+            return Self::Range;
+        }
+
+        // blob
+        if TypeId::of::<T>() == TypeId::of::<Blob>() {
+            return Self::Blob;
+        }
+
         // variant
         Self::Variant(TypeId::of::<T>())
     }
 }
 
 pub struct Mut<T> {
-    lock: Arc<RwLock<Union>>,
+    lock: Shared<Lock<Union>>,
     phantom_data: std::marker::PhantomData<T>,
 }
 
@@ -529,10 +622,7 @@ impl<T: Variant> Mut<T> {
     where
         T: Clone,
     {
-        match self.lock.read() {
-            Ok(union) => union.downcast_ref::<T>().cloned(),
-            Err(_) => None,
-        }
+        read(&self.lock).downcast_ref::<T>().cloned()
     }
 
     #[inline(always)]
@@ -542,23 +632,17 @@ impl<T: Variant> Mut<T> {
 
     #[inline(always)]
     pub fn map<R, F: FnMut(&T) -> R>(&self, mut f: F) -> R {
-        match self.lock.read() {
-            Ok(union) => match union.downcast_ref::<T>() {
-                Some(t) => f(t),
-                None => panic!("unreachable, couldn't reference to T in Mut<T>"),
-            },
-            Err(_) => panic!("Dead lock"),
+        match read(&self.lock).downcast_ref::<T>() {
+            Some(t) => f(t),
+            None => panic!("unreachable, couldn't reference to T in Mut<T>"),
         }
     }
 
     #[inline(always)]
     pub fn map_mut<R, F: FnMut(&mut T) -> R>(&mut self, mut f: F) -> R {
-        match self.lock.write() {
-            Ok(mut union) => match union.downcast_mut::<T>() {
-                Some(t) => f(t),
-                None => panic!("unreachable, couldn't reference to T in Mut<T>"),
-            },
-            Err(_) => panic!("Dead lock"),
+        match write(&self.lock).downcast_mut::<T>() {
+            Some(t) => f(t),
+            None => panic!("unreachable, couldn't reference to T in Mut<T>"),
         }
     }
 }
@@ -567,7 +651,7 @@ impl<T: Variant> Mut<T> {
 #[derive(Debug)]
 pub enum UnionCell {
     Owned(Union),
-    Shared(Arc<RwLock<Union>>),
+    Shared(Shared<Lock<Union>>),
 }
 
 impl UnionCell {
@@ -583,7 +667,7 @@ impl UnionCell {
     #[inline(always)]
     pub fn into_shared(self) -> Self {
         match self {
-            Self::Owned(union) => Self::Shared(Arc::new(RwLock::new(union))),
+            Self::Owned(union) => Self::Shared(Shared::new(Lock::new(union))),
             Self::Shared(_) => self,
         }
     }
@@ -595,7 +679,7 @@ impl UnionCell {
             Self::Shared(_) => return,
         };
 
-        *self = Self::Shared(Arc::new(RwLock::new(union)));
+        *self = Self::Shared(Shared::new(Lock::new(union)));
     }
 
     #[inline(always)]
@@ -650,7 +734,7 @@ impl UnionCell {
     #[inline(always)]
     pub fn into_inner(self) -> Union {
         match self {
-            Self::Shared(shared) => shared.read().unwrap().clone(),
+            Self::Shared(shared) => read(&shared).clone(),
             Self::Owned(union) => union,
         }
     }
@@ -664,7 +748,7 @@ impl UnionCell {
     pub fn set(&mut self, union: Union) {
         match self {
             Self::Owned(owned) => *owned = union,
-            Self::Shared(shared) => *shared.write().unwrap() = union,
+            Self::Shared(shared) => *write(shared) = union,
         }
     }
 
@@ -673,7 +757,7 @@ impl UnionCell {
         match self {
             Self::Owned(union) => f(union),
             Self::Shared(union) => {
-                let union = union.read().expect("Dead Lock");
+                let union = read(union);
 
                 f(&*union)
             }
@@ -685,7 +769,7 @@ impl UnionCell {
         match self {
             Self::Owned(union) => f(union),
             Self::Shared(union) => {
-                let mut union = union.write().expect("Dead Lock");
+                let mut union = write(union);
 
                 f(&mut *union)
             }
@@ -810,12 +894,16 @@ mod test {
         ty!(f32, Float);
         ty!(bool, Bool);
         ty!((), Unit);
+        ty!(Range, Range);
+        ty!(Blob, Blob);
 
         #[derive(Clone, Debug, PartialEq, Default)]
         struct Foo;
 
         ty!(Foo, Variant);
 
+        assert_eq!(Union::new(Foo).downcast::<Foo>(), Some(Foo));
+
         let x = Union::from(3.14);
         let z: Union = x.clone();
         let y = Union::from(z);
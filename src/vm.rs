@@ -0,0 +1,1061 @@
+use crate::ast::*;
+use crate::closure::*;
+use crate::control_flow::*;
+use crate::eval_expr::*;
+use crate::fn_storage::*;
+use crate::function::*;
+use crate::internal_binop::*;
+use crate::module::*;
+use crate::optimize::*;
+use crate::range::*;
+use crate::runtime::*;
+use crate::scope::*;
+use crate::span::*;
+use crate::symbol::*;
+use crate::variant::*;
+use fnv::FnvHashMap;
+use std::sync::Arc;
+
+/// How an [`Engine`](crate::engine::Engine) runs scripts. Both give identical results
+/// and errors, the VM runs [`Op`]s compiled once per script instead of walking the tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Backend {
+    #[default]
+    TreeWalker,
+    Vm,
+}
+
+/// A single instruction of the stack machine. Instructions take their operands from
+/// the top of the stack and push their result.
+///
+/// Every instruction that can fail goes through the same [`Runtime`] helper as the tree
+/// walker, scopes are opened and closed at the same points and operations are counted
+/// in the same order, which is what keeps the two backends identical.
+#[derive(Debug)]
+pub enum Op {
+    /// Counts operations against the limits, one per expression and statement.
+    Count(u32),
+    Push {
+        value: Union,
+        specified: bool,
+    },
+    Pop,
+    /// Collects the top `n` values into an array.
+    Array(usize),
+    Closure {
+        parameter_idents: Arc<Vec<Symbol>>,
+        body: Arc<Spanned<Expr>>,
+        chunk: Arc<Chunk>,
+    },
+    Load {
+        ident: Spanned<Symbol>,
+        slot: Option<Slot>,
+    },
+    Assign(Span),
+    Negate(Span),
+    /// Calls the operator `op`, or applies `builtin` directly to two ints or two floats
+    /// when nothing overloads it for them.
+    BinOp {
+        op: Spanned<Symbol>,
+        site: CallSite,
+        builtin: Option<BuiltinOp>,
+    },
+    Reference,
+    Dereference(Span),
+    Try(Span),
+    Sub,
+    RevSub,
+    Jump(usize),
+    /// Pops the condition of an `if` or `while`, jumping to `target` if it's `false`.
+    JumpIfFalse {
+        target: usize,
+        span: Span,
+    },
+    CheckCancelled,
+    Call {
+        ident: Spanned<Symbol>,
+        slot: Option<Slot>,
+        site: CallSite,
+        argc: usize,
+    },
+    /// Like [`Op::Call`], `argc` includes the caller.
+    CallMethod {
+        ident: Spanned<Symbol>,
+        site: CallSite,
+        argc: usize,
+    },
+    Declare {
+        ident: Symbol,
+        ty: Option<UnionType>,
+        span: Span,
+    },
+    DefineFn {
+        fn_signature: FnSignature,
+        block: Arc<Spanned<Block>>,
+        parameter_idents: Arc<Vec<Symbol>>,
+        return_type: UnionType,
+        chunk: Arc<Chunk>,
+        span: Span,
+    },
+    /// Errors until the matching [`Op::ExitTry`] jump to `catch`.
+    EnterTry {
+        catch: usize,
+    },
+    ExitTry,
+    StartIteration(Spanned<Symbol>),
+    /// Binds the next item of the innermost `for` in a new sub scope, or ends the loop
+    /// by jumping to `end`.
+    NextItem {
+        ident: Symbol,
+        span: Span,
+        end: usize,
+    },
+    RangeBound(Span),
+    MakeRange {
+        start: bool,
+        end: bool,
+        inclusive: bool,
+    },
+    /// Tests the value on top of the stack, jumping to `next` if it doesn't match and
+    /// otherwise popping it into a new sub scope.
+    MatchArm {
+        pattern: Spanned<Pattern>,
+        next: usize,
+    },
+}
+
+/// Operators that neither the functions a chunk is compiled for nor the script itself
+/// overload for two ints or two floats, by name.
+type Builtins = FnvHashMap<Symbol, BuiltinOp>;
+
+/// The instructions of a program, function or closure body. Those of the functions and
+/// closures it defines are compiled along with it.
+#[derive(Debug)]
+pub struct Chunk {
+    pub ops: Vec<Op>,
+}
+
+impl Chunk {
+    /// Compiles `program` without binding any operator, see [`Chunk::compile`].
+    pub fn from_program(program: &Block) -> Self {
+        Compiler::new(&Builtins::default()).program(program)
+    }
+
+    /// Compiles `program` to run with the functions in `module`. Operators that neither
+    /// `module` nor `program` overload for two ints or two floats are bound to their
+    /// built in meaning. Like [`optimize`], this assumes `module` won't change.
+    pub fn compile<T>(program: &Block, module: &Module<T>) -> Self {
+        let defined = defined_fns(program);
+
+        let builtins = BuiltinOp::ALL
+            .iter()
+            .filter_map(|builtin| {
+                // an operator the program doesn't use isn't interned
+                let ident = Symbol::get(builtin.ident())?;
+
+                let overloaded = defined.contains(&ident)
+                    || [UnionType::Int, UnionType::Float].iter().any(|ty| {
+                        let fn_signature = FnSignature {
+                            ident,
+                            params: vec![ty.clone(), ty.clone()],
+                        };
+
+                        module.get_fn(&fn_signature).is_ok()
+                    });
+
+                if overloaded {
+                    None
+                } else {
+                    Some((ident, *builtin))
+                }
+            })
+            .collect();
+
+        Compiler::new(&builtins).program(program)
+    }
+
+    fn from_fn_body(block: &Block) -> Self {
+        let builtins = Builtins::default();
+        let mut compiler = Compiler::new(&builtins);
+        compiler.block(block);
+
+        compiler.finish()
+    }
+
+    fn from_closure_body(body: &Spanned<Expr>) -> Self {
+        let builtins = Builtins::default();
+        let mut compiler = Compiler::new(&builtins);
+        compiler.expr(body);
+
+        compiler.finish()
+    }
+}
+
+struct Compiler<'a> {
+    ops: Vec<Op>,
+    /// First instruction that may be jumped to, counts are never merged across it.
+    label: usize,
+    builtins: &'a Builtins,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(builtins: &'a Builtins) -> Self {
+        Self {
+            ops: Vec::new(),
+            label: 0,
+            builtins,
+        }
+    }
+
+    fn program(mut self, program: &Block) -> Chunk {
+        self.block(program);
+
+        self.finish()
+    }
+
+    fn finish(self) -> Chunk {
+        Chunk { ops: self.ops }
+    }
+
+    /// Compiles the body of a function or closure defined in this chunk.
+    fn nested(&self, compile: impl FnOnce(&mut Compiler<'a>)) -> Arc<Chunk> {
+        let mut compiler = Compiler::new(self.builtins);
+        compile(&mut compiler);
+
+        Arc::new(compiler.finish())
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.ops.push(op);
+        self.ops.len() - 1
+    }
+
+    fn count(&mut self) {
+        let mergeable = self.ops.len() > self.label;
+
+        match self.ops.last_mut() {
+            Some(Op::Count(n)) if mergeable => *n += 1,
+            _ => {
+                self.emit(Op::Count(1));
+            }
+        }
+    }
+
+    /// Position of the next instruction as a jump target.
+    fn here(&mut self) -> usize {
+        self.label = self.ops.len();
+        self.label
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let here = self.here();
+
+        match &mut self.ops[at] {
+            Op::Jump(target)
+            | Op::JumpIfFalse { target, .. }
+            | Op::EnterTry { catch: target }
+            | Op::NextItem { end: target, .. }
+            | Op::MatchArm { next: target, .. } => *target = here,
+            op => unreachable!("{:?} doesn't jump", op),
+        }
+    }
+
+    fn unit(&mut self, specified: bool) {
+        self.emit(Op::Push {
+            value: Union::Unit(()),
+            specified,
+        });
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+
+        match &block.expr {
+            Some(expr) => self.expr(expr),
+            None => self.unit(false),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Spanned<Stmt>) {
+        self.count();
+
+        match &**stmt {
+            Stmt::Let { ident, ty, expr } => {
+                self.expr(expr);
+                self.emit(Op::Declare {
+                    ident: *ident,
+                    ty: ty.clone(),
+                    span: stmt.span,
+                });
+            }
+
+            Stmt::Expr { expr } => {
+                self.expr(expr);
+                self.emit(Op::Pop);
+            }
+
+            Stmt::FnDef {
+                fn_signature,
+                block,
+                parameter_idents,
+                return_type,
+            } => {
+                let chunk = self.nested(|compiler| compiler.block(block));
+
+                self.emit(Op::DefineFn {
+                    fn_signature: fn_signature.clone(),
+                    block: block.clone(),
+                    parameter_idents: parameter_idents.clone(),
+                    return_type: return_type.clone(),
+                    chunk,
+                    span: stmt.span,
+                });
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Spanned<Expr>) {
+        self.count();
+
+        match &**expr {
+            Expr::Literal { variant } => {
+                self.emit(Op::Push {
+                    value: variant.clone(),
+                    specified: false,
+                });
+            }
+
+            Expr::Constant { variant } => {
+                self.emit(Op::Push {
+                    value: variant.clone(),
+                    specified: true,
+                });
+            }
+
+            Expr::Array { items } => {
+                for item in items {
+                    self.expr(item);
+                }
+
+                self.emit(Op::Array(items.len()));
+            }
+
+            Expr::Closure {
+                parameter_idents,
+                body,
+            } => {
+                let chunk = self.nested(|compiler| compiler.expr(body));
+
+                self.emit(Op::Closure {
+                    parameter_idents: parameter_idents.clone(),
+                    body: body.clone(),
+                    chunk,
+                });
+            }
+
+            Expr::Variable { ident, slot } => {
+                self.emit(Op::Load {
+                    ident: ident.clone(),
+                    slot: *slot,
+                });
+            }
+
+            Expr::Assign { target, variable } => {
+                self.expr(target);
+                self.expr(variable);
+                self.emit(Op::Assign(expr.span));
+            }
+
+            Expr::NegationOp { expr } => {
+                self.expr(expr);
+                self.emit(Op::Negate(expr.span));
+            }
+
+            Expr::BinOp { lhs, rhs, op, site } => {
+                self.expr(lhs);
+                self.expr(rhs);
+                self.emit(Op::BinOp {
+                    op: op.clone(),
+                    site: *site,
+                    builtin: self.builtins.get(&op.inner).copied(),
+                });
+            }
+
+            Expr::Reference { expr } => {
+                self.expr(expr);
+                self.emit(Op::Reference);
+            }
+
+            Expr::Dereference { expr } => {
+                self.expr(expr);
+                self.emit(Op::Dereference(expr.span));
+            }
+
+            Expr::Try { expr } => {
+                self.expr(expr);
+                self.emit(Op::Try(expr.span));
+            }
+
+            Expr::Block { block } => {
+                self.emit(Op::Sub);
+                self.block(block);
+                self.emit(Op::RevSub);
+            }
+
+            Expr::If {
+                check,
+                block,
+                else_block,
+            } => {
+                self.expr(check);
+                let to_else = self.emit(Op::JumpIfFalse {
+                    target: 0,
+                    span: check.span,
+                });
+
+                self.block(block);
+                let to_end = self.emit(Op::Jump(0));
+
+                self.patch(to_else);
+
+                match else_block {
+                    Some(else_block) => self.expr(else_block),
+                    None => self.unit(false),
+                }
+
+                self.patch(to_end);
+            }
+
+            Expr::FnCall {
+                ident,
+                params,
+                slot,
+                site,
+            } => {
+                for param in params {
+                    self.expr(param);
+                }
+
+                self.emit(Op::Call {
+                    ident: ident.clone(),
+                    slot: *slot,
+                    site: *site,
+                    argc: params.len(),
+                });
+            }
+
+            Expr::MethodCall {
+                ident,
+                caller,
+                params,
+                site,
+            } => {
+                self.expr(caller);
+
+                for param in params {
+                    self.expr(param);
+                }
+
+                self.emit(Op::CallMethod {
+                    ident: ident.clone(),
+                    site: *site,
+                    argc: params.len() + 1,
+                });
+            }
+
+            Expr::TryCatch {
+                try_block,
+                catch_block,
+            } => {
+                let enter = self.emit(Op::EnterTry { catch: 0 });
+
+                self.block(try_block);
+                self.emit(Op::ExitTry);
+                self.emit(Op::Pop);
+                self.unit(true);
+                let to_end = self.emit(Op::Jump(0));
+
+                self.patch(enter);
+                self.block(catch_block);
+
+                self.patch(to_end);
+            }
+
+            Expr::WhileLoop { expr, block } => {
+                let start = self.here();
+
+                self.expr(expr);
+                let to_end = self.emit(Op::JumpIfFalse {
+                    target: 0,
+                    span: expr.span,
+                });
+
+                self.emit(Op::CheckCancelled);
+                self.emit(Op::Sub);
+                self.block(block);
+                self.emit(Op::Pop);
+                self.emit(Op::RevSub);
+                self.emit(Op::Jump(start));
+
+                self.patch(to_end);
+                self.unit(true);
+            }
+
+            Expr::ForLoop { ident, expr, block } => {
+                self.expr(expr);
+                self.emit(Op::StartIteration(ident.clone()));
+
+                let next = self.emit(Op::NextItem {
+                    ident: ident.inner,
+                    span: expr.span,
+                    end: 0,
+                });
+
+                self.block(block);
+                self.emit(Op::Pop);
+                self.emit(Op::RevSub);
+                self.emit(Op::Jump(next));
+
+                self.patch(next);
+                self.unit(false);
+            }
+
+            Expr::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                for bound in start.iter().chain(end.iter()) {
+                    self.expr(bound);
+                    self.emit(Op::RangeBound(bound.span));
+                }
+
+                self.emit(Op::MakeRange {
+                    start: start.is_some(),
+                    end: end.is_some(),
+                    inclusive: *inclusive,
+                });
+            }
+
+            Expr::Match { expr, arms } => {
+                self.expr(expr);
+
+                let mut to_end = Vec::with_capacity(arms.len());
+
+                for arm in arms {
+                    let test = self.emit(Op::MatchArm {
+                        pattern: arm.pattern.clone(),
+                        next: 0,
+                    });
+
+                    self.expr(&arm.expr);
+                    self.emit(Op::RevSub);
+                    to_end.push(self.emit(Op::Jump(0)));
+
+                    self.patch(test);
+                }
+
+                self.emit(Op::Pop);
+                self.unit(false);
+
+                for jump in to_end {
+                    self.patch(jump);
+                }
+            }
+        }
+    }
+}
+
+struct Handler {
+    catch: usize,
+    stack: usize,
+    iterations: usize,
+    /// Sub scopes open when the `try` started.
+    depth: usize,
+}
+
+/// State of one chunk being run.
+struct Frame<T> {
+    pc: usize,
+    stack: Vec<Variable>,
+    iterations: Vec<Iteration<T>>,
+    handlers: Vec<Handler>,
+}
+
+impl<T> Frame<T> {
+    fn pop(&mut self) -> Variable {
+        self.stack.pop().expect("vm stack underflow")
+    }
+
+    fn pop_n(&mut self, n: usize) -> Vec<Variable> {
+        self.stack.split_off(self.stack.len() - n)
+    }
+}
+
+impl<'a, T> Runtime<'a, T> {
+    /// Runs the body of a script function with the configured [`Backend`], the VM runs
+    /// `chunk` if it was compiled already.
+    pub(crate) fn eval_fn_body(
+        &mut self,
+        block: &Spanned<Block>,
+        chunk: Option<&Chunk>,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        match (self.backend, chunk) {
+            (Backend::TreeWalker, _) => self.eval_block(block, scope),
+            (Backend::Vm, Some(chunk)) => self.run_chunk(chunk, scope),
+            (Backend::Vm, None) => self.run_chunk(&Chunk::from_fn_body(block), scope),
+        }
+    }
+
+    /// Runs the body of a closure like [`Runtime::eval_fn_body`].
+    pub(crate) fn eval_closure_body(
+        &mut self,
+        body: &Spanned<Expr>,
+        chunk: Option<&Chunk>,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        match (self.backend, chunk) {
+            (Backend::TreeWalker, _) => self.eval_expr(body, scope),
+            (Backend::Vm, Some(chunk)) => self.run_chunk(chunk, scope),
+            (Backend::Vm, None) => self.run_chunk(&Chunk::from_closure_body(body), scope),
+        }
+    }
+
+    pub fn run_chunk(
+        &mut self,
+        chunk: &Chunk,
+        scope: &mut Scope<T>,
+    ) -> Result<Variable, ControlFlow> {
+        let depth = scope.depth();
+
+        let mut frame = Frame {
+            pc: 0,
+            stack: self.stacks.pop().unwrap_or_default(),
+            iterations: Vec::new(),
+            handlers: Vec::new(),
+        };
+
+        while let Some(op) = chunk.ops.get(frame.pc) {
+            frame.pc += 1;
+
+            if let Err(flow) = self.step(op, &mut frame, scope) {
+                // like the tree walker, `try` only catches errors, and the sub scopes
+                // the flow skipped are closed either way
+                let handler = match flow {
+                    ControlFlow::Error(_) => frame.handlers.pop(),
+                    _ => None,
+                };
+
+                match handler {
+                    Some(handler) => {
+                        frame.stack.truncate(handler.stack);
+                        frame.iterations.truncate(handler.iterations);
+                        scope.rev_sub_to(handler.depth);
+                        frame.pc = handler.catch;
+                    }
+                    None => {
+                        scope.rev_sub_to(depth);
+                        self.reuse_stack(frame.stack);
+
+                        return Err(flow);
+                    }
+                }
+            }
+        }
+
+        let returned = frame.pop();
+        self.reuse_stack(frame.stack);
+
+        Ok(returned)
+    }
+
+    fn reuse_stack(&mut self, mut stack: Vec<Variable>) {
+        stack.clear();
+        self.stacks.push(stack);
+    }
+
+    #[inline(always)]
+    fn step(
+        &mut self,
+        op: &Op,
+        frame: &mut Frame<T>,
+        scope: &mut Scope<T>,
+    ) -> Result<(), ControlFlow> {
+        match op {
+            Op::Count(n) => self.count_operations(u64::from(*n))?,
+
+            Op::Push { value, specified } => {
+                frame.stack.push(Variable::new(value.clone(), *specified));
+            }
+
+            Op::Pop => {
+                frame.pop();
+            }
+
+            Op::Array(n) => {
+                let array = frame
+                    .pop_n(*n)
+                    .into_iter()
+                    .map(|item| UnionCell::Owned(item.into_inner()))
+                    .collect::<Vec<_>>();
+
+                frame.stack.push(Variable::unspecified(Union::from(array)));
+            }
+
+            Op::Closure {
+                parameter_idents,
+                body,
+                chunk,
+            } => {
+                let closure = Closure {
+                    parameter_idents: parameter_idents.clone(),
+                    body: body.clone(),
+                    captured: scope.capture(),
+                    chunk: Some(chunk.clone()),
+                };
+
+                frame
+                    .stack
+                    .push(Variable::unspecified(Union::from(closure)));
+            }
+
+            Op::Load { ident, slot } => {
+                let variable = self.load_variable(ident, *slot, scope)?;
+                frame.stack.push(variable);
+            }
+
+            Op::Assign(span) => {
+                let variable = frame.pop();
+                let target = frame.pop();
+
+                let unit = self.assign(*span, target, variable)?;
+                frame.stack.push(unit);
+            }
+
+            Op::Negate(span) => {
+                let variable = frame.pop();
+
+                let negated = self.negate(*span, variable, scope)?;
+                frame.stack.push(negated);
+            }
+
+            Op::BinOp { op, site, builtin } => {
+                let rhs = frame.pop();
+                let lhs = frame.pop();
+
+                let applied = builtin.and_then(|builtin| apply_builtin(builtin, &lhs, &rhs));
+
+                let variable = match applied {
+                    Some(union) => Variable::specified(union),
+                    // other types may still be overloaded or fail
+                    None => self.call_binop(op, *site, lhs, rhs, scope)?,
+                };
+                frame.stack.push(variable);
+            }
+
+            Op::Reference => {
+                let variable = frame.pop();
+                frame.stack.push(reference(variable));
+            }
+
+            Op::Dereference(span) => {
+                let variable = frame.pop();
+
+                let dereferenced = self.dereference(*span, variable)?;
+                frame.stack.push(dereferenced);
+            }
+
+            Op::Try(span) => {
+                let variable = frame.pop();
+
+                let unwrapped = self.try_unwrap(*span, variable)?;
+                frame.stack.push(unwrapped);
+            }
+
+            Op::Sub => scope.sub(false),
+
+            Op::RevSub => scope.rev_sub(),
+
+            Op::Jump(target) => frame.pc = *target,
+
+            Op::JumpIfFalse { target, span } => {
+                let variable = frame.pop();
+
+                if !self.check(*span, &variable)? {
+                    frame.pc = *target;
+                }
+            }
+
+            Op::CheckCancelled => self.check_cancelled()?,
+
+            Op::Call {
+                ident,
+                slot,
+                site,
+                argc,
+            } => {
+                let params = frame.pop_n(*argc);
+
+                let returned = self.call_named(ident, *slot, *site, params, scope)?;
+                frame.stack.push(returned);
+            }
+
+            Op::CallMethod { ident, site, argc } => {
+                let params = frame.pop_n(*argc);
+
+                let returned = self.call_method(ident, *site, params, scope)?;
+                frame.stack.push(returned);
+            }
+
+            Op::Declare { ident, ty, span } => {
+                let variable = frame.pop();
+
+                self.declare(*ident, ty.as_ref(), *span, variable, scope)?;
+            }
+
+            Op::DefineFn {
+                fn_signature,
+                block,
+                parameter_idents,
+                return_type,
+                chunk,
+                span,
+            } => {
+                let fn_type = FnType::<T>::Native {
+                    block: block.clone(),
+                    parameter_idents: parameter_idents.clone(),
+                    return_type: return_type.clone(),
+                    chunk: Some(chunk.clone()),
+                };
+
+                self.define_fn(fn_signature, fn_type, *span, scope)?;
+            }
+
+            Op::EnterTry { catch } => frame.handlers.push(Handler {
+                catch: *catch,
+                stack: frame.stack.len(),
+                iterations: frame.iterations.len(),
+                depth: scope.depth(),
+            }),
+
+            Op::ExitTry => {
+                frame.handlers.pop();
+            }
+
+            Op::StartIteration(ident) => {
+                let iterable = frame.pop();
+
+                let iteration = self.start_iteration(ident, iterable, scope)?;
+                frame.iterations.push(iteration);
+            }
+
+            Op::NextItem { ident, span, end } => {
+                let iteration = frame.iterations.last().expect("vm iteration underflow");
+
+                match self.next_item(iteration, *span, scope)? {
+                    Some(union) => {
+                        scope.sub(false);
+                        scope.push(*ident, Variable::unspecified(union));
+                    }
+                    None => {
+                        frame.iterations.pop();
+                        frame.pc = *end;
+                    }
+                }
+            }
+
+            Op::RangeBound(span) => {
+                let variable = frame.pop();
+
+                let bound = self.range_bound(*span, &variable)?;
+                frame.stack.push(Variable::unspecified(Union::Int(bound)));
+            }
+
+            Op::MakeRange {
+                start,
+                end,
+                inclusive,
+            } => {
+                let mut bound = |present: bool| {
+                    if present {
+                        frame.pop().map(Union::as_int)
+                    } else {
+                        None
+                    }
+                };
+
+                // the end was pushed last
+                let end = bound(*end);
+                let start = bound(*start);
+
+                frame.stack.push(Variable::unspecified(Union::Range(Range {
+                    start,
+                    end,
+                    inclusive: *inclusive,
+                })));
+            }
+
+            Op::MatchArm { pattern, next } => {
+                let value = frame.stack.last().expect("vm stack underflow");
+
+                if self.matches_pattern(pattern, value, scope)? {
+                    let value = frame.pop();
+
+                    scope.sub(false);
+
+                    if let Pattern::Binding(ident) = &**pattern {
+                        scope.push(*ident, value);
+                    }
+                } else {
+                    frame.pc = *next;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Applies `builtin` if both operands are ints or floats.
+#[inline(always)]
+fn apply_builtin(builtin: BuiltinOp, lhs: &Variable, rhs: &Variable) -> Option<Union> {
+    lhs.map(|lhs| match lhs {
+        Union::Int(_) | Union::Float(_) => rhs.map(|rhs| builtin.apply(lhs, rhs)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::*;
+
+    fn run(backend: Backend, source: &str) -> (Result<String, (String, String)>, u64) {
+        let mut program = crate::grammar::BlockParser::new()
+            .parse(source)
+            .expect(source);
+        crate::resolve::resolve(&mut program);
+
+        let mut scope = Scope::new();
+        scope.register_module("std", crate::iron_std::iron_std());
+        scope.merge_module(crate::iron_std::global_full());
+
+        let mut ctx = ();
+        let mut runtime = Runtime::new(&mut ctx, source.to_string());
+        runtime.backend = backend;
+
+        let result = runtime
+            .run(&program, &mut scope)
+            .map(|union| union.to_string())
+            .map_err(|err: Error| (format!("{:?}", err.kind), err.code));
+
+        (result, runtime.operations)
+    }
+
+    #[test]
+    fn same_as_tree_walker() {
+        let sources = [
+            "1 + 2 * 3",
+            "let a = [1, 2, 3]; a.push(4); a[3] + a.len()",
+            "fn fib(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } } fib(12)",
+            "let s = \"\"; for i in 0..5 { s = s + i.to_string(); } s",
+            "let i = 0; while i < 10 { i += 1; } i",
+            "let f = |x| x * 2; let g = |y| f(y) + 1; g(20)",
+            "let a = [3, 1, 2]; a.iter().map(|x| x * 10).collect()",
+            "match 5 { 0 => \"zero\", 1..10 => \"small\", _ => \"big\" }",
+            "match 7 { n => n + 1 }",
+            "let r = 2..=4; [r.contains(4), r.len()]",
+            "fn half(x) { if x % 2 == 0 { ok(x / 2) } else { err(x) } } fn q(x) { ok(half(half(x)?)?) } [q(8), q(6)]",
+            "let r = 0; try { let x = 1; r = x; undefined_fn(); } catch { r = r + 1; } r",
+            "fn fail() { let y = 2; nope() } let r = 0; try { fail(); } catch { r = 3; } r",
+            "let x: i32 = 1; x = \"no\"",
+            "if 1 { 2 } else { 3 }",
+            "1..\"a\"",
+            "*5",
+            "5?",
+            "!3",
+            "let v = 1; { let v = 2; } v",
+            "let v = 1; if true { let v = 2; } v",
+            "let i = 0; let a = []; while i < 3 { let j = i * 2; a.push(|| j); i += 1; } let g = a[1]; g()",
+            "fn f(x) { let a = 1; try { let b = 2; nope(); } catch { } let c = 3; [x, a, c] } f(0)",
+            "let hp = 7; format(\"{hp}/{}\", 10)",
+            "fn g(x) { 1 } let s = 0; let i = 0; while i < 2 { s = s * 10 + g(i); if i == 0 { fn g(x: i32) { 2 } } i += 1; } s",
+            "let a = [1]; let r = &a; (*r).push(2); a",
+            "for x in 5 { x; }",
+            "std::math::PI > 3.0",
+            "missing",
+            "fn f(x: i32) { x } f(\"a\")",
+            "std::sys::exit(3)",
+            "try { std::sys::exit(4); } catch { }",
+            "fn f(x) { try { let y = x?; } catch { } ok(5) } f(err(1))",
+        ];
+
+        // the tree walker needs a lot of stack per call in debug builds
+        let compare = std::thread::Builder::new()
+            .stack_size(64 << 20)
+            .spawn(move || {
+                for source in sources {
+                    let walked = run(Backend::TreeWalker, source);
+                    let compiled = run(Backend::Vm, source);
+
+                    assert_eq!(walked, compiled, "{}", source);
+                }
+            });
+
+        compare.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn engine_backend() {
+        let mut engine = crate::engine::Engine::<()>::new();
+        engine.set_backend(Backend::Vm);
+
+        let source = "fn total(a) { let t = 0; for x in a { t += x; } t } total([1, 2, 3])";
+
+        assert_eq!(engine.eval(&mut (), source).unwrap().to_string(), "6");
+    }
+
+    #[test]
+    fn bound_operators() {
+        let sources = [
+            "let a = 7; [a + 2, a - 2, a * 2, a / 2, a % 2, a < 2, a >= 7, a == 7, a != 7]",
+            "let f = 1.5; [f + 1.0, f * 2.0, f > 1.0, 1 + f]",
+            "let s = \"a\"; s + 1 + 2",
+            "fn +(a: i32, b: i32) { a * b } let x = 2; x + 3",
+            "fn *(a: f32, b: f32) { a + b } let x = 2; [x * 3, 2.0 * 3.0]",
+            "fn add(a, b) { a + b } let g = |x| add(x, 1) * 2; [add(1, 2), g(3), g(4)]",
+        ];
+
+        let mut walker = crate::engine::Engine::<()>::new();
+        walker.register_fn("-", |a: i32, b: i32| a + b);
+
+        let mut vm = crate::engine::Engine::<()>::new();
+        vm.register_fn("-", |a: i32, b: i32| a + b)
+            .set_backend(Backend::Vm);
+
+        for source in sources {
+            let script = walker.compile(source).unwrap();
+            let expected = walker.run(&mut (), &script).map(|u| u.to_string());
+
+            // the compiled chunks are shared by every run of the script
+            for _ in 0..2 {
+                let result = vm.run(&mut (), &script).map(|u| u.to_string());
+
+                assert_eq!(
+                    format!("{:?}", result),
+                    format!("{:?}", expected),
+                    "{}",
+                    source
+                );
+            }
+        }
+
+        // `-` was registered for ints, so it isn't bound to subtracting them
+        let script = vm.compile("let a = 5; a - 2").unwrap();
+        assert_eq!(vm.run(&mut (), &script).unwrap().to_string(), "7");
+    }
+}